@@ -1,36 +1,50 @@
 use std::env;
 
+const INT_OR_STRING: &str = "#[serde(deserialize_with = \"crate::kaspad_messages::int_or_string\")]";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let protowire_files = &["proto/messages.proto", "proto/rpc.proto", "proto/p2p.proto"];
     let dirs = &["proto"];
 
     println!("cargo:rerun-if-changed=proto");
     println!("cargo:rerun-if-changed=src/asm");
-    tonic_build::configure()
-        .build_server(false)
-        .build_client(true)
-        // .protoc_arg("--experimental_allow_proto3_optional") // no need for this if the proto files using optional are not passed directly to tonic_build
-        // .type_attribute(".", "#[derive(Debug)]")
-        .compile_protos(&protowire_files[0..1], dirs)?;
+    // The `no_std` PoW core doesn't talk to kaspad
+    if env::var_os("CARGO_FEATURE_STD").is_some() {
+        tonic_build::configure()
+            .build_server(false)
+            .build_client(true)
+            // .protoc_arg("--experimental_allow_proto3_optional") // no need for this if the proto files using optional are not passed directly to tonic_build
+            // .type_attribute(".", "#[derive(Debug)]")
+            // JSON in kaspad's field names, for the commands reading blocks and templates from files
+            .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+            .message_attribute(".", "#[serde(default, rename_all = \"camelCase\")]")
+            // kaspad's JSON has 64 bit integers as strings
+            .field_attribute(".protowire.RpcBlockHeader.timestamp", INT_OR_STRING)
+            .field_attribute(".protowire.RpcBlockHeader.nonce", INT_OR_STRING)
+            .field_attribute(".protowire.RpcBlockHeader.daaScore", INT_OR_STRING)
+            .field_attribute(".protowire.RpcBlockHeader.blueScore", INT_OR_STRING)
+            .compile_protos(&protowire_files[0..1], dirs)?;
 
-    for file in protowire_files {
-        println!("cargo:rerun-if-changed={file}");
+        for file in protowire_files {
+            println!("cargo:rerun-if-changed={file}");
+        }
     }
     let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
     let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap();
 
-    if target_arch == "x86_64" {
-        let mut builder = cc::Build::new();
-        builder.flag("-c");
-        match target_os.as_str() {
-            "macos" => builder.file("src/asm/keccakf1600_x86-64-osx.s"),
-            "linux" => builder.file("src/asm/keccakf1600_x86-64-elf.s"),
-            "windows" if target_env == "gnu" => builder.file("src/asm/keccakf1600_x86-64-mingw64.s"),
-            "windows" if target_env == "msvc" => builder.file("src/asm/keccakf1600_x86-64-msvc.asm"),
-            _ => unimplemented!("Unsupported OS"),
+    println!("cargo:rustc-check-cfg=cfg(keccak_asm)");
+    if target_arch == "x86_64" && env::var_os("CARGO_FEATURE_NO_ASM").is_none() {
+        let asm = match target_os.as_str() {
+            "macos" => "src/asm/keccakf1600_x86-64-osx.s",
+            "linux" => "src/asm/keccakf1600_x86-64-elf.s",
+            "windows" if target_env == "gnu" => "src/asm/keccakf1600_x86-64-mingw64.s",
+            "windows" if target_env == "msvc" => "src/asm/keccakf1600_x86-64-msvc.asm",
+            // e.g. `x86_64-unknown-none`, which falls back to the keccak crate
+            _ => return Ok(()),
         };
-        builder.compile("libkeccak.a");
+        cc::Build::new().flag("-c").file(asm).compile("libkeccak.a");
+        println!("cargo:rustc-cfg=keccak_asm");
     }
     Ok(())
 }
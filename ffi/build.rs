@@ -0,0 +1,12 @@
+use std::{env, path::PathBuf};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file("cbindgen.toml")?;
+    // Generated outside the source tree, tests/header.rs checks the committed include/kaspa_pow.h is the same
+    let header = PathBuf::from(env::var("OUT_DIR")?).join("kaspa_pow.h");
+    cbindgen::Builder::new().with_config(config).with_src("src/lib.rs").generate()?.write_to_file(&header);
+    println!("cargo:rustc-env=KASPA_POW_HEADER={}", header.display());
+    Ok(())
+}
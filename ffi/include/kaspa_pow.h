@@ -0,0 +1,107 @@
+#ifndef KASPA_POW_H
+#define KASPA_POW_H
+
+/* Generated by cbindgen from src/lib.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of the functions that can fail
+typedef enum KaspaResult {
+  KASPA_RESULT_OK = 0,
+  // A required pointer was null
+  KASPA_RESULT_NULL_POINTER = 1,
+} KaspaResult;
+
+// The kHeavyHash matrix of a pre-PoW hash, to hash many nonces of the same header with
+typedef struct KaspaMatrix KaspaMatrix;
+
+// The hashes of one level of a header's parents
+typedef struct KaspaParentLevel {
+  const uint8_t (*hashes)[32];
+  size_t hashes_len;
+} KaspaParentLevel;
+
+// A block header with its hashes already decoded from hex
+typedef struct KaspaHeader {
+  uint16_t version;
+  const struct KaspaParentLevel *parents;
+  size_t parents_len;
+  uint8_t hash_merkle_root[32];
+  uint8_t accepted_id_merkle_root[32];
+  uint8_t utxo_commitment[32];
+  uint64_t timestamp;
+  uint32_t bits;
+  uint64_t nonce;
+  uint64_t daa_score;
+  // Big-endian, as long as it's sent in kaspad's messages
+  const uint8_t *blue_work;
+  size_t blue_work_len;
+  uint64_t blue_score;
+  uint8_t pruning_point[32];
+} KaspaHeader;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Writes the hash identifying the block, of its whole header, to `out`.
+//
+// # Safety
+// `header` must point to a valid header whose `parents` and `blue_work` point to as many elements as their lengths,
+// and `out` to 32 writable bytes.
+enum KaspaResult kaspa_block_hash(const struct KaspaHeader *header,
+                                  uint8_t *out);
+
+// Writes the hash of the header without its timestamp and nonce, which the matrix is generated from, to `out`.
+//
+// # Safety
+// The same as [`kaspa_block_hash`].
+enum KaspaResult kaspa_pre_pow_hash(const struct KaspaHeader *header,
+                                    uint8_t *out);
+
+// Writes the kHeavyHash result of the header with its timestamp and nonce to `out`.
+// Generating the matrix is slow, use [`kaspa_matrix_new`] and [`kaspa_heavy_hash`] for many nonces.
+//
+// # Safety
+// The same as [`kaspa_block_hash`].
+enum KaspaResult kaspa_calculate_pow(const struct KaspaHeader *header, uint8_t *out);
+
+// Generates the kHeavyHash matrix of a pre-PoW hash, to be freed with [`kaspa_matrix_free`].
+// Returns null if `pre_pow_hash` is.
+//
+// # Safety
+// `pre_pow_hash` must point to 32 readable bytes.
+struct KaspaMatrix *kaspa_matrix_new(const uint8_t *pre_pow_hash);
+
+// Frees a matrix from [`kaspa_matrix_new`], doing nothing for null.
+//
+// # Safety
+// `matrix` must come from [`kaspa_matrix_new`] and not be used after this.
+void kaspa_matrix_free(struct KaspaMatrix *matrix);
+
+// Writes the kHeavyHash result of a nonce to `out`, with the matrix generated from `pre_pow_hash`.
+//
+// # Safety
+// `matrix` must come from [`kaspa_matrix_new`], `pre_pow_hash` must point to 32 readable bytes and `out` to 32 writable ones.
+enum KaspaResult kaspa_heavy_hash(const struct KaspaMatrix *matrix,
+                                  const uint8_t *pre_pow_hash,
+                                  uint64_t timestamp,
+                                  uint64_t nonce,
+                                  uint8_t *out);
+
+// Whether the PoW isn't above the target of the compact `bits`, i.e. the block is valid. False if `pow` is null.
+//
+// # Safety
+// `pow` must point to 32 readable bytes.
+bool kaspa_check_pow(const uint8_t *pow,
+                     uint32_t bits);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KASPA_POW_H */
@@ -0,0 +1,180 @@
+//! A C ABI for the kHeavyHash proof of work, the same as the kaspa-miner computes, with its header in `include/kaspa_pow.h`.
+//!
+//! Hashes are passed as their 32 bytes, in the order kaspad prints them in hex, which is a little-endian integer for the PoW.
+use kaspa_miner::{
+    pow::{HeaderView, Matrix},
+    target, Hash,
+};
+use std::slice;
+
+/// The result of the functions that can fail
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KaspaResult {
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+}
+
+/// The hashes of one level of a header's parents
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct KaspaParentLevel {
+    pub hashes: *const [u8; 32],
+    pub hashes_len: usize,
+}
+
+/// A block header with its hashes already decoded from hex
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct KaspaHeader {
+    pub version: u16,
+    pub parents: *const KaspaParentLevel,
+    pub parents_len: usize,
+    pub hash_merkle_root: [u8; 32],
+    pub accepted_id_merkle_root: [u8; 32],
+    pub utxo_commitment: [u8; 32],
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub daa_score: u64,
+    /// Big-endian, as long as it's sent in kaspad's messages
+    pub blue_work: *const u8,
+    pub blue_work_len: usize,
+    pub blue_score: u64,
+    pub pruning_point: [u8; 32],
+}
+
+/// The kHeavyHash matrix of a pre-PoW hash, to hash many nonces of the same header with
+pub struct KaspaMatrix(Matrix);
+
+/// A slice from C, which may pass null for an empty one.
+unsafe fn slice_from_raw<'a, T>(ptr: *const T, len: usize) -> Option<&'a [T]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Calls `f` with the header's [`HeaderView`], or returns `NullPointer` if one of its pointers is.
+unsafe fn with_view(header: *const KaspaHeader, out: *mut u8, f: impl FnOnce(&HeaderView) -> Hash) -> KaspaResult {
+    let (Some(header), Some(out)) = (header.as_ref(), out.cast::<[u8; 32]>().as_mut()) else {
+        return KaspaResult::NullPointer;
+    };
+    let Some(levels) = slice_from_raw(header.parents, header.parents_len) else {
+        return KaspaResult::NullPointer;
+    };
+    let Some(parents) =
+        levels.iter().map(|level| slice_from_raw(level.hashes, level.hashes_len)).collect::<Option<Vec<_>>>()
+    else {
+        return KaspaResult::NullPointer;
+    };
+    let Some(blue_work) = slice_from_raw(header.blue_work, header.blue_work_len) else {
+        return KaspaResult::NullPointer;
+    };
+    let view = HeaderView {
+        version: header.version,
+        parents: &parents,
+        hash_merkle_root: header.hash_merkle_root,
+        accepted_id_merkle_root: header.accepted_id_merkle_root,
+        utxo_commitment: header.utxo_commitment,
+        timestamp: header.timestamp,
+        bits: header.bits,
+        nonce: header.nonce,
+        daa_score: header.daa_score,
+        blue_work,
+        blue_score: header.blue_score,
+        pruning_point: header.pruning_point,
+    };
+    *out = f(&view).to_le_bytes();
+    KaspaResult::Ok
+}
+
+/// Writes the hash identifying the block, of its whole header, to `out`.
+///
+/// # Safety
+/// `header` must point to a valid header whose `parents` and `blue_work` point to as many elements as their lengths,
+/// and `out` to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_block_hash(header: *const KaspaHeader, out: *mut u8) -> KaspaResult {
+    with_view(header, out, |view| view.block_hash())
+}
+
+/// Writes the hash of the header without its timestamp and nonce, which the matrix is generated from, to `out`.
+///
+/// # Safety
+/// The same as [`kaspa_block_hash`].
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_pre_pow_hash(header: *const KaspaHeader, out: *mut u8) -> KaspaResult {
+    with_view(header, out, |view| view.pre_pow_hash())
+}
+
+/// Writes the kHeavyHash result of the header with its timestamp and nonce to `out`.
+/// Generating the matrix is slow, use [`kaspa_matrix_new`] and [`kaspa_heavy_hash`] for many nonces.
+///
+/// # Safety
+/// The same as [`kaspa_block_hash`].
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_calculate_pow(header: *const KaspaHeader, out: *mut u8) -> KaspaResult {
+    with_view(header, out, |view| view.calculate_pow())
+}
+
+/// Generates the kHeavyHash matrix of a pre-PoW hash, to be freed with [`kaspa_matrix_free`].
+/// Returns null if `pre_pow_hash` is.
+///
+/// # Safety
+/// `pre_pow_hash` must point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_matrix_new(pre_pow_hash: *const u8) -> *mut KaspaMatrix {
+    match pre_pow_hash.cast::<[u8; 32]>().as_ref() {
+        Some(pre_pow_hash) => {
+            Box::into_raw(Box::new(KaspaMatrix(Matrix::generate(Hash::from_le_bytes(*pre_pow_hash)))))
+        }
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a matrix from [`kaspa_matrix_new`], doing nothing for null.
+///
+/// # Safety
+/// `matrix` must come from [`kaspa_matrix_new`] and not be used after this.
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_matrix_free(matrix: *mut KaspaMatrix) {
+    if !matrix.is_null() {
+        drop(Box::from_raw(matrix));
+    }
+}
+
+/// Writes the kHeavyHash result of a nonce to `out`, with the matrix generated from `pre_pow_hash`.
+///
+/// # Safety
+/// `matrix` must come from [`kaspa_matrix_new`], `pre_pow_hash` must point to 32 readable bytes and `out` to 32 writable ones.
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_heavy_hash(
+    matrix: *const KaspaMatrix,
+    pre_pow_hash: *const u8,
+    timestamp: u64,
+    nonce: u64,
+    out: *mut u8,
+) -> KaspaResult {
+    let (Some(matrix), Some(pre_pow_hash), Some(out)) =
+        (matrix.as_ref(), pre_pow_hash.cast::<[u8; 32]>().as_ref(), out.cast::<[u8; 32]>().as_mut())
+    else {
+        return KaspaResult::NullPointer;
+    };
+    *out = matrix.0.pow(Hash::from_le_bytes(*pre_pow_hash), timestamp, nonce).to_le_bytes();
+    KaspaResult::Ok
+}
+
+/// Whether the PoW isn't above the target of the compact `bits`, i.e. the block is valid. False if `pow` is null.
+///
+/// # Safety
+/// `pow` must point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn kaspa_check_pow(pow: *const u8, bits: u32) -> bool {
+    match pow.cast::<[u8; 32]>().as_ref() {
+        Some(pow) => Hash::from_le_bytes(*pow) <= target::u256_from_compact_target(bits),
+        None => false,
+    }
+}
@@ -0,0 +1,75 @@
+/* Prints the hashes of a fixed header for tests/c_api.rs to compare with the Rust ones. */
+#include <stdio.h>
+#include <string.h>
+
+#include "kaspa_pow.h"
+
+#define CHECK(cond)                                                                                                    \
+    if (!(cond)) {                                                                                                     \
+        fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);                                       \
+        return 1;                                                                                                      \
+    }
+
+static void print_hash(const char *name, const uint8_t hash[32]) {
+    printf("%s ", name);
+    for (int i = 0; i < 32; i++) {
+        printf("%02x", hash[i]);
+    }
+    printf("\n");
+}
+
+int main(void) {
+    uint8_t level0[2][32], level1[1][32];
+    memset(level0[0], 0x01, 32);
+    memset(level0[1], 0x02, 32);
+    memset(level1[0], 0x03, 32);
+    KaspaParentLevel parents[2] = {{level0, 2}, {level1, 1}};
+    const uint8_t blue_work[3] = {0x01, 0x02, 0x03};
+
+    KaspaHeader header;
+    memset(&header, 0, sizeof(header));
+    header.version = 1;
+    header.parents = parents;
+    header.parents_len = 2;
+    memset(header.hash_merkle_root, 0x04, 32);
+    memset(header.accepted_id_merkle_root, 0x05, 32);
+    memset(header.utxo_commitment, 0x06, 32);
+    header.timestamp = 1234;
+    header.bits = 0x207fffff;
+    header.nonce = 42;
+    header.daa_score = 7;
+    header.blue_work = blue_work;
+    header.blue_work_len = 3;
+    header.blue_score = 9;
+    memset(header.pruning_point, 0x07, 32);
+
+    uint8_t block_hash[32], pre_pow_hash[32], pow[32], heavy_hash[32];
+    CHECK(kaspa_block_hash(&header, block_hash) == KASPA_RESULT_OK);
+    CHECK(kaspa_pre_pow_hash(&header, pre_pow_hash) == KASPA_RESULT_OK);
+    CHECK(kaspa_calculate_pow(&header, pow) == KASPA_RESULT_OK);
+
+    KaspaMatrix *matrix = kaspa_matrix_new(pre_pow_hash);
+    CHECK(matrix != NULL);
+    CHECK(kaspa_heavy_hash(matrix, pre_pow_hash, header.timestamp, header.nonce, heavy_hash) == KASPA_RESULT_OK);
+    CHECK(memcmp(pow, heavy_hash, 32) == 0);
+    kaspa_matrix_free(matrix);
+
+    print_hash("block_hash", block_hash);
+    print_hash("pre_pow_hash", pre_pow_hash);
+    print_hash("pow", pow);
+    printf("valid %d\n", kaspa_check_pow(pow, header.bits));
+
+    uint8_t zero[32] = {0}, max[32];
+    memset(max, 0xff, 32);
+    CHECK(kaspa_check_pow(zero, 0x1d00ffff));
+    CHECK(!kaspa_check_pow(max, 0x1d00ffff));
+    CHECK(!kaspa_check_pow(NULL, 0x1d00ffff));
+
+    CHECK(kaspa_block_hash(NULL, block_hash) == KASPA_RESULT_NULL_POINTER);
+    CHECK(kaspa_block_hash(&header, NULL) == KASPA_RESULT_NULL_POINTER);
+    header.blue_work = NULL;
+    CHECK(kaspa_block_hash(&header, block_hash) == KASPA_RESULT_NULL_POINTER);
+    CHECK(kaspa_matrix_new(NULL) == NULL);
+    kaspa_matrix_free(NULL);
+    return 0;
+}
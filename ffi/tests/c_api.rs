@@ -0,0 +1,61 @@
+//! Builds tests/c/test.c against the static library and checks it gets the same hashes as the `kaspa_miner` crate.
+#![cfg(unix)]
+
+use kaspa_miner::{pow::HeaderView, target, Hash};
+use std::{env, path::Path, process::Command};
+
+fn hex(hash: Hash) -> String {
+    hash.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn test_c_api() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // The static library is built next to the test binary, in `target/<profile>/deps`
+    let exe = env::current_exe().unwrap();
+    let deps_dir = exe.parent().unwrap();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("kaspa_pow_c_test");
+
+    let mut cc = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()));
+    cc.arg(manifest_dir.join("tests/c/test.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(deps_dir.join("libkaspa_pow.a"))
+        .arg("-o")
+        .arg(&program);
+    if cfg!(target_os = "linux") {
+        cc.args(["-lpthread", "-ldl", "-lm"]);
+    }
+    let status = cc.status().expect("A C compiler is needed to run the C test");
+    assert!(status.success(), "Failed compiling the C test");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(output.status.success(), "The C test failed: {}", String::from_utf8_lossy(&output.stderr));
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    let blue_work = [1, 2, 3];
+    let parents: [&[[u8; 32]]; 2] = [&[[1; 32], [2; 32]], &[[3; 32]]];
+    let header = HeaderView {
+        version: 1,
+        parents: &parents,
+        hash_merkle_root: [4; 32],
+        accepted_id_merkle_root: [5; 32],
+        utxo_commitment: [6; 32],
+        timestamp: 1234,
+        bits: 0x207fffff,
+        nonce: 42,
+        daa_score: 7,
+        blue_work: &blue_work,
+        blue_score: 9,
+        pruning_point: [7; 32],
+    };
+    let pow = header.calculate_pow();
+    let expected = format!(
+        "block_hash {}\npre_pow_hash {}\npow {}\nvalid {}\n",
+        hex(header.block_hash()),
+        hex(header.pre_pow_hash()),
+        hex(pow),
+        u8::from(pow <= target::u256_from_compact_target(header.bits))
+    );
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,15 @@
+//! Checks the committed include/kaspa_pow.h is the one cbindgen generates from the current API.
+
+use std::{fs, path::Path};
+
+#[test]
+fn test_header_up_to_date() {
+    let generated = env!("KASPA_POW_HEADER");
+    let committed = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/kaspa_pow.h");
+    let read = |path: &Path| fs::read_to_string(path).unwrap().replace("\r\n", "\n");
+    assert!(
+        read(Path::new(generated)) == read(&committed),
+        "include/kaspa_pow.h is out of date, copy the generated {} over it",
+        generated
+    );
+}
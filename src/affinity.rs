@@ -0,0 +1,179 @@
+use crate::Error;
+use std::{collections::HashSet, fs, io, path::Path, str::FromStr};
+
+pub const SYSFS_CPU_PATH: &str = "/sys/devices/system/cpu";
+
+/// A list of logical CPUs, in the kernel's list format (e.g. `0-3,8,10-11`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CpuList(pub Vec<usize>);
+
+impl FromStr for CpuList {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = "CPU list should look like 0-3,8,10-11";
+        let mut cpus = Vec::new();
+        for range in s.trim().split(',').filter(|range| !range.is_empty()) {
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => (range, range),
+            };
+            let start: usize = start.trim().parse().map_err(|_| err)?;
+            let end: usize = end.trim().parse().map_err(|_| err)?;
+            if start > end {
+                return Err(err);
+            }
+            cpus.extend(start..=end);
+        }
+        if cpus.is_empty() {
+            return Err(err);
+        }
+        Ok(Self(cpus))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuAffinity {
+    /// One thread per physical core, as read from sysfs
+    Auto,
+    /// Threads are pinned to these CPUs in order, wrapping around if there are more threads than CPUs
+    List(CpuList),
+}
+
+impl FromStr for CpuAffinity {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            list => Ok(Self::List(list.parse()?)),
+        }
+    }
+}
+
+impl CpuAffinity {
+    /// Returns the CPUs the miner threads should be pinned to, the n'th thread goes to the n'th CPU.
+    pub fn resolve(&self, reserved: &CpuList) -> Result<Vec<usize>, Error> {
+        let cpus = match self {
+            Self::Auto => physical_cores(Path::new(SYSFS_CPU_PATH), &reserved.0)?,
+            Self::List(list) => list.0.iter().copied().filter(|cpu| !reserved.0.contains(cpu)).collect(),
+        };
+        if cpus.is_empty() {
+            return Err("No CPUs left to pin miner threads to".into());
+        }
+        Ok(cpus)
+    }
+}
+
+/// Picks a single logical CPU for every physical core described under `sysfs_root`.
+///
+/// SMT siblings are skipped, and so is every core that has one of its threads in `reserved`,
+/// as a miner thread on a sibling would still compete with whatever the reserved CPU is running.
+pub fn physical_cores(sysfs_root: &Path, reserved: &[usize]) -> io::Result<Vec<usize>> {
+    let mut cpus: Vec<usize> = fs::read_dir(sysfs_root)?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_prefix("cpu")?.parse().ok())
+        .collect();
+    cpus.sort_unstable();
+
+    let mut seen = HashSet::new();
+    let mut cores = Vec::new();
+    for cpu in cpus {
+        if seen.contains(&cpu) {
+            continue;
+        }
+        let siblings_path = sysfs_root.join(format!("cpu{cpu}/topology/thread_siblings_list"));
+        let siblings = match fs::read_to_string(&siblings_path) {
+            Ok(siblings) => siblings.parse::<CpuList>().map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", siblings_path.display(), e))
+            })?,
+            // Offline CPUs don't have a topology directory.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        seen.insert(cpu);
+        seen.extend(siblings.0.iter().copied());
+        if reserved.contains(&cpu) || siblings.0.iter().any(|sibling| reserved.contains(sibling)) {
+            continue;
+        }
+        cores.push(cpu);
+    }
+    Ok(cores)
+}
+
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) -> Result<(), Error> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(format!("CPU {} is out of range", cpu).into());
+    }
+    // SAFETY: `cpu_set_t` is a plain bitmask, all zeros is the empty set, and `cpu` was checked to fit in it.
+    let res = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) -> Result<(), Error> {
+    Err("CPU affinity is only supported on Linux".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::affinity::{physical_cores, CpuAffinity, CpuList};
+    use std::{fs, path::PathBuf};
+
+    fn fake_sysfs(name: &str, siblings: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("kaspa-miner-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (cpu, siblings) in siblings.iter().enumerate() {
+            let topology = root.join(format!("cpu{cpu}/topology"));
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("thread_siblings_list"), format!("{siblings}\n")).unwrap();
+        }
+        // Entries that aren't CPUs are ignored
+        fs::create_dir_all(root.join("cpufreq")).unwrap();
+        fs::write(root.join("online"), "0-7\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!("0-3,8,10-11".parse(), Ok(CpuList(vec![0, 1, 2, 3, 8, 10, 11])));
+        assert_eq!("5\n".parse(), Ok(CpuList(vec![5])));
+        assert!("".parse::<CpuList>().is_err());
+        assert!("3-1".parse::<CpuList>().is_err());
+        assert!("a,b".parse::<CpuList>().is_err());
+        assert_eq!("auto".parse(), Ok(CpuAffinity::Auto));
+        assert_eq!("1,3".parse(), Ok(CpuAffinity::List(CpuList(vec![1, 3]))));
+    }
+
+    #[test]
+    fn test_physical_cores() {
+        // 4 cores with 2 threads each, siblings are numbered N and N+4 like on most x86 machines.
+        let root = fake_sysfs("smt", &["0,4", "1,5", "2,6", "3,7", "0,4", "1,5", "2,6", "3,7"]);
+        assert_eq!(physical_cores(&root, &[]).unwrap(), vec![0, 1, 2, 3]);
+        // Reserving a thread reserves its whole core
+        assert_eq!(physical_cores(&root, &[5]).unwrap(), vec![0, 2, 3]);
+        assert_eq!(physical_cores(&root, &[0, 3]).unwrap(), vec![1, 2]);
+
+        // Offline CPUs have no topology
+        fs::remove_dir_all(root.join("cpu2/topology")).unwrap();
+        assert_eq!(physical_cores(&root, &[]).unwrap(), vec![0, 1, 3, 6]);
+        fs::remove_dir_all(&root).unwrap();
+
+        // Siblings numbered next to each other
+        let root = fake_sysfs("adjacent", &["0-1", "0-1", "2-3", "2-3"]);
+        assert_eq!(physical_cores(&root, &[]).unwrap(), vec![0, 2]);
+        fs::remove_dir_all(&root).unwrap();
+
+        // No SMT
+        let root = fake_sysfs("no-smt", &["0", "1", "2"]);
+        assert_eq!(physical_cores(&root, &[1]).unwrap(), vec![0, 2]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
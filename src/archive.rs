@@ -0,0 +1,227 @@
+use crate::{json_file, proto::RpcBlock, target::Uint256, Error, Hash};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// How many blocks the archive keeps by default, the oldest ones are deleted first
+pub const DEFAULT_MAX_BLOCKS: usize = 1000;
+
+const INDEX_FILE: &str = "index.json";
+
+/// What happened to a found block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Submission {
+    /// Submitted, kaspad didn't respond yet
+    Pending,
+    Accepted,
+    Rejected {
+        reject_reason: String,
+        error: String,
+    },
+    NotSubmitted {
+        reason: String,
+    },
+}
+
+/// A found block with what's needed to inspect it later, each in its own file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedBlock {
+    pub block_hash: String,
+    pub template_id: usize,
+    pub pay_address: Option<String>,
+    pub pow: String,
+    pub target: String,
+    /// Milliseconds since the unix epoch
+    pub found_at: i64,
+    pub submission: Submission,
+    pub block: RpcBlock,
+}
+
+impl ArchivedBlock {
+    pub fn new(
+        block: RpcBlock,
+        block_hash: Hash,
+        template_id: usize,
+        pay_address: Option<String>,
+        pow: Uint256,
+        target: Uint256,
+        found_at: i64,
+    ) -> Self {
+        Self {
+            block_hash: format!("{:x}", block_hash),
+            template_id,
+            pay_address,
+            pow: format!("{:x}", pow),
+            target: format!("{:x}", target),
+            found_at,
+            submission: Submission::Pending,
+            block,
+        }
+    }
+}
+
+/// A line of the index, from the oldest block to the newest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub file: String,
+    pub block_hash: String,
+    pub found_at: i64,
+    pub submission: Submission,
+}
+
+/// A directory with a JSON file for every found block, and an index of them.
+#[derive(Debug)]
+pub struct Archive {
+    dir: PathBuf,
+    max_blocks: usize,
+    index: Mutex<Vec<IndexEntry>>,
+}
+
+impl Archive {
+    /// Opens the archive, continuing the index of previous runs.
+    pub fn open(dir: PathBuf, max_blocks: usize) -> Result<Self, Error> {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed creating the archive {}: {}", dir.display(), e))?;
+        let index = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(index) => serde_json::from_slice(&index)
+                .map_err(|e| format!("Failed parsing the archive index in {}: {}", dir.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed reading the archive index in {}: {}", dir.display(), e).into()),
+        };
+        Ok(Self { dir, max_blocks, index: Mutex::new(index) })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves a found block, deleting the oldest ones above the maximum.
+    pub fn add(&self, block: &ArchivedBlock) -> Result<(), Error> {
+        let file = format!("{}-{}.json", block.found_at, block.block_hash);
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        json_file::write(&self.dir.join(&file), block)?;
+        index.push(IndexEntry {
+            file,
+            block_hash: block.block_hash.clone(),
+            found_at: block.found_at,
+            submission: block.submission.clone(),
+        });
+        // Only the blocks whose files are gone leave the index, so it never loses track of a file
+        let excess = index.len().saturating_sub(self.max_blocks);
+        let mut deleted = Ok(());
+        let mut removed = 0;
+        for old in &index[..excess] {
+            match fs::remove_file(self.dir.join(&old.file)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    deleted = Err(format!("Failed deleting {} from the archive: {}", old.file, e));
+                    break;
+                }
+                _ => removed += 1,
+            }
+        }
+        index.drain(..removed);
+        json_file::write(&self.dir.join(INDEX_FILE), &*index)?;
+        Ok(deleted?)
+    }
+
+    /// Records kaspad's response to the latest pending block with this hash.
+    pub fn set_submission(&self, block_hash: Hash, submission: Submission) -> Result<(), Error> {
+        let block_hash = format!("{:x}", block_hash);
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = index
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.block_hash == block_hash && entry.submission == Submission::Pending)
+        else {
+            return Ok(());
+        };
+        let path = self.dir.join(&entry.file);
+        let mut block: ArchivedBlock = serde_json::from_slice(&fs::read(&path)?)?;
+        block.submission = submission.clone();
+        json_file::write(&path, &block)?;
+        entry.submission = submission;
+        json_file::write(&self.dir.join(INDEX_FILE), &*index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        archive::{Archive, ArchivedBlock, IndexEntry, Submission, INDEX_FILE},
+        proto::RpcBlock,
+        Hash,
+    };
+    use std::fs;
+
+    #[test]
+    fn test_archive() {
+        let dir = std::env::temp_dir().join(format!("kaspa-miner-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let block = |i: u64| {
+            let pay_address = Some("kaspa:miner".to_string());
+            ArchivedBlock::new(
+                RpcBlock::default(),
+                Hash::from_u64(i),
+                i as usize,
+                pay_address,
+                Hash::from_u64(i),
+                Hash::from_u64(9),
+                i as i64,
+            )
+        };
+
+        let archive = Archive::open(dir.clone(), 2).unwrap();
+        archive.add(&block(1)).unwrap();
+        archive.add(&block(2)).unwrap();
+        archive.set_submission(Hash::from_u64(2), Submission::Accepted).unwrap();
+        // Another run continues the index, and rotates out the oldest block
+        let archive = Archive::open(dir.clone(), 2).unwrap();
+        archive.add(&block(3)).unwrap();
+        let rejected = Submission::Rejected { reject_reason: "BLOCK_INVALID".to_string(), error: "bad".to_string() };
+        archive.set_submission(Hash::from_u64(3), rejected.clone()).unwrap();
+
+        let index = archive.index.lock().unwrap().clone();
+        assert_eq!(index.iter().map(|entry| entry.found_at).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(index[0].submission, Submission::Accepted);
+        assert!(!dir.join(format!("1-{:x}.json", Hash::from_u64(1))).exists());
+        let saved: ArchivedBlock = serde_json::from_slice(&fs::read(dir.join(&index[1].file)).unwrap()).unwrap();
+        assert_eq!(saved, ArchivedBlock { submission: rejected, ..block(3) });
+        assert_eq!(serde_json::from_slice::<Vec<IndexEntry>>(&fs::read(dir.join(INDEX_FILE)).unwrap()).unwrap(), index);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_rotation_failure() {
+        let dir = std::env::temp_dir().join(format!("kaspa-miner-archive-rotation-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let block = |i: u64| {
+            ArchivedBlock::new(
+                RpcBlock::default(),
+                Hash::from_u64(i),
+                0,
+                None,
+                Hash::default(),
+                Hash::default(),
+                i as i64,
+            )
+        };
+        let archive = Archive::open(dir.clone(), 1).unwrap();
+        archive.add(&block(1)).unwrap();
+        // A directory in place of the oldest block can't be deleted like a file
+        let oldest = dir.join(&archive.index.lock().unwrap()[0].file);
+        fs::remove_file(&oldest).unwrap();
+        fs::create_dir_all(oldest.join("stuck")).unwrap();
+        assert!(archive.add(&block(2)).is_err());
+        let index = archive.index.lock().unwrap().clone();
+        assert_eq!(index.iter().map(|entry| entry.found_at).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(serde_json::from_slice::<Vec<IndexEntry>>(&fs::read(dir.join(INDEX_FILE)).unwrap()).unwrap(), index);
+
+        fs::remove_dir_all(&oldest).unwrap();
+        archive.add(&block(3)).unwrap();
+        assert_eq!(archive.index.lock().unwrap().iter().map(|entry| entry.found_at).collect::<Vec<_>>(), [3]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{
+    cli::BenchOpt,
+    miner::{get_num_cpus, MinerManager},
+    pow,
+    proto::{RpcBlock, RpcBlockHeader},
+    Error,
+};
+use rand::{thread_rng, Rng, RngCore};
+use serde::Serialize;
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often each thread samples its hashrate, the variance is computed over these samples
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize)]
+pub struct BenchResult {
+    pub version: &'static str,
+    pub keccak: &'static str,
+    pub arch: &'static str,
+    pub os: &'static str,
+    pub num_threads: u16,
+    pub duration_secs: f64,
+    /// Total hashrate of all the threads, over the samples
+    pub total: HashrateStats,
+    pub threads: Vec<HashrateStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashrateStats {
+    pub hashes: u64,
+    /// Hashes per second over the whole run
+    pub hashrate: f64,
+    /// Hashes per second, of each sample
+    pub samples: Vec<f64>,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl HashrateStats {
+    fn new(hashes: u64, elapsed: Duration, samples: Vec<f64>) -> Self {
+        let mean = samples.iter().sum::<f64>() / samples.len().max(1) as f64;
+        let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / samples.len().max(1) as f64;
+        Self {
+            hashes,
+            hashrate: hashes as f64 / elapsed.as_secs_f64(),
+            std_dev: variance.sqrt(),
+            min: samples.iter().copied().reduce(f64::min).unwrap_or_default(),
+            max: samples.iter().copied().reduce(f64::max).unwrap_or_default(),
+            samples,
+        }
+    }
+
+    /// The standard deviation as a percentage of the hashrate
+    pub fn relative_std_dev(&self) -> f64 {
+        if self.hashrate == 0.0 {
+            return 0.0;
+        }
+        self.std_dev / self.hashrate * 100.0
+    }
+}
+
+/// A template with random hashes that will never be solved, so every thread mines its own matrix like in real mining.
+pub fn synthetic_state() -> pow::State {
+    let mut rng = thread_rng();
+    let mut random_hash = || {
+        let mut hash = [0u8; 32];
+        rng.fill_bytes(&mut hash);
+        hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
+    };
+    let block = RpcBlock {
+        header: Some(RpcBlockHeader {
+            version: 1,
+            parents: vec![],
+            hash_merkle_root: random_hash(),
+            accepted_id_merkle_root: random_hash(),
+            utxo_commitment: random_hash(),
+            timestamp: 654654353,
+            // The smallest possible target
+            bits: 0x03000001,
+            nonce: 0,
+            daa_score: 654456,
+            blue_work: "d8e28a03234786".to_string(),
+            pruning_point: random_hash(),
+            blue_score: 1164419,
+        }),
+        transactions: vec![],
+        verbose_data: None,
+    };
+    pow::State::new(0, block).expect("The synthetic header is valid")
+}
+
+/// Hashes synthetic templates on `num_threads` threads for `duration`.
+pub fn benchmark(num_threads: u16, duration: Duration) -> BenchResult {
+    let start = Instant::now();
+    let deadline = start + duration;
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let mut state = synthetic_state();
+            state.nonce = thread_rng().gen();
+            thread::spawn(move || {
+                let start = Instant::now();
+                let mut hashes = 0u64;
+                let mut samples = Vec::new();
+                let (mut sample_start, mut sample_hashes) = (start, 0u64);
+                loop {
+                    for _ in 0..128 {
+                        black_box(state.check_pow());
+                        state.nonce = state.nonce.wrapping_add(1);
+                    }
+                    sample_hashes += 128;
+                    let now = Instant::now();
+                    if now >= sample_start + SAMPLE_INTERVAL || now >= deadline {
+                        samples.push(sample_hashes as f64 / (now - sample_start).as_secs_f64());
+                        hashes += sample_hashes;
+                        (sample_start, sample_hashes) = (now, 0);
+                    }
+                    if now >= deadline {
+                        return (hashes, now - start, samples);
+                    }
+                }
+            })
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|handle| handle.join().expect("Benchmark thread panicked")).collect();
+    let elapsed = start.elapsed();
+
+    // The threads sample at roughly the same times, so the n'th samples of all threads add up to the total.
+    let num_samples = results.iter().map(|(_, _, samples)| samples.len()).min().unwrap_or_default();
+    let total_samples =
+        (0..num_samples).map(|i| results.iter().map(|(_, _, samples)| samples[i]).sum::<f64>()).collect();
+    let total_hashes = results.iter().map(|(hashes, _, _)| hashes).sum();
+
+    BenchResult {
+        version: env!("CARGO_PKG_VERSION"),
+        keccak: if cfg!(keccak_asm) { "asm" } else { "portable" },
+        arch: std::env::consts::ARCH,
+        os: std::env::consts::OS,
+        num_threads,
+        duration_secs: elapsed.as_secs_f64(),
+        total: HashrateStats::new(total_hashes, elapsed, total_samples),
+        threads: results
+            .into_iter()
+            .map(|(hashes, elapsed, samples)| HashrateStats::new(hashes, elapsed, samples))
+            .collect(),
+    }
+}
+
+pub fn run(opt: &BenchOpt) -> Result<(), Error> {
+    let num_threads = get_num_cpus(opt.num_threads);
+    if !opt.json {
+        println!("Benchmarking {} threads for {} seconds", num_threads, opt.duration);
+    }
+    let result = benchmark(num_threads, Duration::from_secs(opt.duration));
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+    let format_rate = |rate: f64| {
+        let (rate, suffix) = MinerManager::hash_suffix(rate);
+        format!("{:.2} {}", rate, suffix)
+    };
+    for (i, thread) in result.threads.iter().enumerate() {
+        println!("Thread {}: {} ± {:.2}%", i, format_rate(thread.hashrate), thread.relative_std_dev());
+    }
+    println!(
+        "Total: {} ± {:.2}% (min: {}, max: {}), keccak: {}",
+        format_rate(result.total.hashrate),
+        result.total.relative_std_dev(),
+        format_rate(result.total.min),
+        format_rate(result.total.max),
+        result.keccak
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::{benchmark, HashrateStats};
+    use std::time::Duration;
+
+    #[test]
+    fn test_hashrate_stats() {
+        let stats = HashrateStats::new(3000, Duration::from_secs(3), vec![900.0, 1000.0, 1100.0]);
+        assert_eq!(stats.hashrate, 1000.0);
+        assert_eq!(stats.min, 900.0);
+        assert_eq!(stats.max, 1100.0);
+        assert!((stats.std_dev - 81.649_658_092_772_6).abs() < 1e-9);
+        assert!((stats.relative_std_dev() - 8.164_965_809_277_26).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_benchmark() {
+        let result = benchmark(2, Duration::from_millis(100));
+        assert_eq!(result.threads.len(), 2);
+        assert!(result.threads.iter().all(|thread| thread.hashes > 0 && thread.samples.len() == 1));
+        assert_eq!(result.total.hashes, result.threads.iter().map(|thread| thread.hashes).sum::<u64>());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::throttle::{Throttle, TokenBucket};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The miner threads check the throttle every this many hashes, templates needing fewer hashes per block are paced
+/// block by block instead, or a single batch would find many blocks at once
+const BATCH_WORK: f64 = 128.0;
+/// How much of the gap between the actual and the target interval is corrected at once
+const CORRECTION_GAIN: f64 = 0.25;
+/// The most the hashrate is corrected by, either way
+const MAX_CORRECTION: f64 = 4.0;
+/// The throttle keeps the hashrate a bit under the cap, so it's only too slow when it's well below it
+const SLOW_HASHRATE: f64 = 0.9;
+
+/// Caps the hashrate so blocks are found every `interval` on average, following the target of the current template,
+/// and corrects it from the interval the blocks are actually found at.
+#[derive(Debug)]
+pub struct BlockRate {
+    interval: Duration,
+    bucket: Arc<TokenBucket>,
+    blocks: Mutex<FoundBlocks>,
+    control: Mutex<Control>,
+}
+
+#[derive(Debug, Default)]
+struct FoundBlocks {
+    count: u64,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Control {
+    block_work: f64,
+    /// How much faster to hash than the template's difficulty says, from the blocks found so far
+    correction: f64,
+    /// How many blocks were found at the last correction
+    corrected_at: u64,
+    /// When the next block can be found, if blocks are paced one by one
+    next_block: Option<Instant>,
+}
+
+impl BlockRate {
+    pub fn new(interval: Duration) -> Self {
+        // The rate is only used once there's a template to mine, which sets it
+        Self {
+            interval,
+            bucket: Arc::new(TokenBucket::new(1.0)),
+            blocks: Mutex::default(),
+            control: Mutex::new(Control { block_work: 0.0, correction: 1.0, corrected_at: 0, next_block: None }),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn throttle(self: &Arc<Self>) -> Throttle {
+        Throttle::BlockRate(Arc::clone(self))
+    }
+
+    fn control(&self) -> std::sync::MutexGuard<'_, Control> {
+        self.control.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_rate(&self, control: &Control) {
+        self.bucket.set_rate(control.block_work * control.correction / self.interval.as_secs_f64());
+    }
+
+    /// Adjusts the hashrate to a template needing `block_work` hashes per block.
+    pub fn set_block_work(&self, block_work: f64) {
+        let mut control = self.control();
+        control.block_work = block_work;
+        if block_work >= BATCH_WORK {
+            control.next_block = None;
+        }
+        self.set_rate(&control);
+    }
+
+    /// The hashrate needed to find blocks at the target interval
+    pub fn hashrate(&self) -> f64 {
+        self.bucket.rate()
+    }
+
+    /// Takes `hashes` hashed at `now`, returning how long to sleep until hashing on. Templates easier than a batch
+    /// wait for the next block instead of their hashes.
+    pub fn wait(&self, hashes: u64, now: Instant) -> Duration {
+        let control = self.control();
+        if control.block_work >= BATCH_WORK {
+            drop(control);
+            return self.bucket.take(hashes, now);
+        }
+        control.next_block.map_or(Duration::ZERO, |next_block| next_block.saturating_duration_since(now))
+    }
+
+    /// Claims a block found at `now`, which is only submitted if it's time for the next one when pacing blocks
+    /// one by one. The threads that found one in the same batch drop theirs.
+    pub fn claim(&self, now: Instant) -> bool {
+        let mut control = self.control();
+        if control.block_work >= BATCH_WORK {
+            return true;
+        }
+        if control.next_block.is_some_and(|next_block| now < next_block) {
+            return false;
+        }
+        control.next_block = Some(now + self.interval.div_f64(control.correction));
+        true
+    }
+
+    pub fn block_found(&self, now: Instant) {
+        let mut blocks = self.blocks.lock().unwrap_or_else(|e| e.into_inner());
+        blocks.count += 1;
+        blocks.first.get_or_insert(now);
+        blocks.last = Some(now);
+    }
+
+    /// The average interval between the blocks found so far, and how many were found.
+    pub fn actual_interval(&self) -> (Option<Duration>, u64) {
+        let blocks = self.blocks.lock().unwrap_or_else(|e| e.into_inner());
+        let interval = match (blocks.first, blocks.last) {
+            (Some(first), Some(last)) if blocks.count > 1 => Some((last - first) / (blocks.count - 1) as u32),
+            _ => None,
+        };
+        (interval, blocks.count)
+    }
+
+    /// Corrects the hashrate towards the target interval once more blocks were found, given the measured `hashrate`.
+    /// Returns whether the measured hashrate is too slow for the target interval.
+    pub fn correct(&self, hashrate: f64) -> bool {
+        let too_slow = hashrate < self.hashrate() * SLOW_HASHRATE;
+        let (Some(actual), found) = self.actual_interval() else {
+            return too_slow;
+        };
+        let mut control = self.control();
+        if found <= control.corrected_at {
+            return too_slow;
+        }
+        control.corrected_at = found;
+        let ratio = (actual.as_secs_f64() / self.interval.as_secs_f64()).powf(CORRECTION_GAIN);
+        // Asking for more than the threads can do wouldn't find blocks any faster
+        if ratio < 1.0 || !too_slow {
+            control.correction = (control.correction * ratio).clamp(1.0 / MAX_CORRECTION, MAX_CORRECTION);
+            self.set_rate(&control);
+        }
+        too_slow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_rate::BlockRate, target, throttle::Throttle};
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_block_rate() {
+        let block_rate = Arc::new(BlockRate::new(Duration::from_secs(5)));
+        block_rate.set_block_work(1_000_000.0);
+        assert_eq!(block_rate.hashrate(), 200_000.0);
+        // The miner threads share it, so they follow the difficulty changes
+        let Throttle::BlockRate(throttle) = block_rate.throttle() else { panic!("Expected a block rate") };
+        block_rate.set_block_work(2_000_000.0);
+        assert_eq!(throttle.hashrate(), 400_000.0);
+
+        let start = Instant::now();
+        // Paced by the hashes, whenever blocks are found
+        assert_eq!(block_rate.wait(0, start), Duration::ZERO);
+        assert!(block_rate.claim(start));
+        assert!(block_rate.claim(start));
+        assert_eq!(block_rate.actual_interval(), (None, 0));
+        block_rate.block_found(start);
+        assert_eq!(block_rate.actual_interval(), (None, 1));
+        block_rate.block_found(start + Duration::from_secs(4));
+        block_rate.block_found(start + Duration::from_secs(12));
+        assert_eq!(block_rate.actual_interval(), (Some(Duration::from_secs(6)), 3));
+    }
+
+    #[test]
+    fn test_block_rate_correction() {
+        let block_rate = BlockRate::new(Duration::from_secs(5));
+        block_rate.set_block_work(1_000_000.0);
+        let start = Instant::now();
+        block_rate.block_found(start);
+        block_rate.block_found(start + Duration::from_secs(10));
+        // Blocks are found every 10s instead of 5s, so it hashes faster, as far as the threads keep up
+        assert!(block_rate.correct(100_000.0));
+        assert_eq!(block_rate.hashrate(), 200_000.0);
+        assert!(!block_rate.correct(200_000.0));
+        assert_eq!(block_rate.hashrate(), 200_000.0);
+        block_rate.block_found(start + Duration::from_secs(20));
+        assert!(!block_rate.correct(200_000.0));
+        let faster = 200_000.0 * 2f64.powf(0.25);
+        assert!((block_rate.hashrate() - faster).abs() < 1e-6);
+        // It's kept for the next templates
+        block_rate.set_block_work(2_000_000.0);
+        assert!((block_rate.hashrate() - 2.0 * faster).abs() < 1e-6);
+
+        // Blocks found too fast slow it down again, even if the threads are slower than the cap
+        (0..20).for_each(|_| block_rate.block_found(start + Duration::from_secs(20)));
+        assert!(block_rate.correct(0.0));
+        assert!(block_rate.hashrate() < 2.0 * faster);
+    }
+
+    #[test]
+    fn test_block_rate_devnet() {
+        let block_rate = BlockRate::new(Duration::from_secs(5));
+        // A devnet's easiest bits need about 2 hashes per block, so a single batch of hashes would find dozens
+        let block_work = target::expected_hashes(target::u256_from_compact_target(0x207fffff));
+        assert!(block_work < 3.0);
+        block_rate.set_block_work(block_work);
+
+        // Capping the hashrate would make a batch of 128 hashes wait minutes
+        assert!(block_rate.hashrate() < 1.0);
+        let start = Instant::now();
+        // Hashing is only paused once a block is found, no matter how many hashes were taken
+        assert_eq!(block_rate.wait(128, start), Duration::ZERO);
+        assert!(block_rate.claim(start));
+        // The other blocks found until the threads pause are dropped
+        assert!(!block_rate.claim(start + Duration::from_millis(1)));
+        assert_eq!(block_rate.wait(128, start + Duration::from_secs(1)), Duration::from_secs(4));
+        assert_eq!(block_rate.wait(128, start + Duration::from_secs(5)), Duration::ZERO);
+        assert!(block_rate.claim(start + Duration::from_secs(5)));
+        assert!(!block_rate.claim(start + Duration::from_secs(9)));
+
+        // Blocks found too slow shorten the pause
+        block_rate.block_found(start);
+        block_rate.block_found(start + Duration::from_secs(10));
+        block_rate.correct(f64::INFINITY);
+        assert!(block_rate.claim(start + Duration::from_secs(10)));
+        let pause = Duration::from_secs(5).div_f64(2f64.powf(0.25));
+        assert_eq!(block_rate.wait(0, start + Duration::from_secs(10)), pause);
+
+        // A harder template goes back to capping the hashrate
+        block_rate.set_block_work(1_000_000.0);
+        assert_eq!(block_rate.wait(0, start + Duration::from_secs(10)), Duration::ZERO);
+        assert!(block_rate.claim(start + Duration::from_secs(10)));
+    }
+}
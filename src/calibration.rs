@@ -0,0 +1,171 @@
+use crate::{bench, json_file, miner::MinerManager, Error};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How long each thread count is benchmarked for
+const BURST: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub threads: u16,
+    pub hashrate: f64,
+}
+
+/// The calibrations of every machine (and miner build) that shares the cache file
+type CalibrationCache = BTreeMap<String, Calibration>;
+
+/// Picks the amount of threads with the highest total hashrate, calibrating only if this machine wasn't calibrated before.
+/// `usable_cpus` caps the candidates, from the cgroup limits and the CPUs the threads are pinned to.
+///
+/// Calibrating takes seconds of hashing, so it should run on a blocking thread.
+pub fn auto_threads(usable_cpus: Option<usize>) -> u16 {
+    let key = machine_key(usable_cpus);
+    let cache_path = cache_path();
+    if let Some(cached) = cache_path.as_deref().and_then(|path| load(path, &key)) {
+        info!("Using {} miner threads, as calibrated on a previous run", cached.threads);
+        return cached.threads;
+    }
+
+    let candidates = candidates(num_cpus::get_physical(), num_cpus::get(), usable_cpus);
+    info!(
+        "Calibrating the amount of miner threads, this will take {} seconds",
+        BURST.as_secs() * candidates.len() as u64
+    );
+    let calibration = calibrate(&candidates, BURST);
+    let (rate, suffix) = MinerManager::hash_suffix(calibration.hashrate);
+    info!("Using {} miner threads, which got the highest hashrate: {:.2} {}", calibration.threads, rate, suffix);
+
+    match &cache_path {
+        Some(path) => {
+            if let Err(e) = store(path, key, calibration) {
+                warn!("Failed caching the thread calibration in {}: {}", path.display(), e);
+            }
+        }
+        None => warn!("Couldn't find a cache directory, the threads will be calibrated again on the next run"),
+    }
+    calibration.threads
+}
+
+/// The thread counts worth trying, between half the physical cores and all the logical ones, up to the usable CPUs.
+pub fn candidates(physical: usize, logical: usize, usable: Option<usize>) -> Vec<u16> {
+    let usable = usable.unwrap_or(usize::MAX).max(1);
+    let physical = physical.clamp(1, usable);
+    let logical = logical.clamp(physical, usable);
+    let mut candidates: Vec<u16> = [physical / 2, physical, (physical + logical) / 2, logical]
+        .into_iter()
+        .filter_map(|threads| u16::try_from(threads.max(1)).ok())
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates
+}
+
+pub fn calibrate(candidates: &[u16], burst: Duration) -> Calibration {
+    candidates
+        .iter()
+        .map(|&threads| {
+            let result = bench::benchmark(threads, burst);
+            let (rate, suffix) = MinerManager::hash_suffix(result.total.hashrate);
+            info!("{} threads: {:.2} {}", threads, rate, suffix);
+            Calibration { threads, hashrate: result.total.hashrate }
+        })
+        .reduce(|best, calibration| if calibration.hashrate > best.hashrate { calibration } else { best })
+        .expect("There's always at least a single candidate")
+}
+
+/// A calibration is only valid for the same CPUs, limits and build of the miner.
+fn machine_key(usable_cpus: Option<usize>) -> String {
+    let cpu_model = fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| {
+            let line = cpuinfo.lines().find(|line| line.starts_with("model name"))?;
+            Some(line.split_once(':')?.1.trim().to_string())
+        })
+        .unwrap_or_else(|| env::consts::ARCH.to_string());
+    let keccak = if cfg!(keccak_asm) { "asm" } else { "portable" };
+    let usable = usable_cpus.map_or_else(String::new, |cpus| format!("{}-usable/", cpus));
+    format!(
+        "{}/{}-physical/{}-logical/{}{}-{}",
+        cpu_model,
+        num_cpus::get_physical(),
+        num_cpus::get(),
+        usable,
+        env!("CARGO_PKG_VERSION"),
+        keccak
+    )
+}
+
+fn cache_path() -> Option<PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_dir.join("kaspa-miner").join("auto-threads.json"))
+}
+
+fn load(path: &Path, key: &str) -> Option<Calibration> {
+    let cache: CalibrationCache = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    cache.get(key).copied()
+}
+
+fn store(path: &Path, key: String, calibration: Calibration) -> Result<(), Error> {
+    let mut cache: CalibrationCache =
+        fs::read(path).ok().and_then(|cache| serde_json::from_slice(&cache).ok()).unwrap_or_default();
+    cache.insert(key, calibration);
+    json_file::write(path, &cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibration::{calibrate, candidates, load, store, Calibration};
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(candidates(8, 16, None), vec![4, 8, 12, 16]);
+        assert_eq!(candidates(4, 4, None), vec![2, 4]);
+        assert_eq!(candidates(1, 2, None), vec![1, 2]);
+        assert_eq!(candidates(1, 1, None), vec![1]);
+        assert_eq!(candidates(0, 0, None), vec![1]);
+        // A cgroup or the pinned CPUs cap them
+        assert_eq!(candidates(8, 16, Some(10)), vec![4, 8, 9, 10]);
+        assert_eq!(candidates(8, 16, Some(2)), vec![1, 2]);
+        assert_eq!(candidates(8, 16, Some(0)), vec![1]);
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let calibration = calibrate(&[1, 2], Duration::from_millis(50));
+        assert!([1, 2].contains(&calibration.threads));
+        assert!(calibration.hashrate > 0.0);
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("kaspa-miner-calibration-{}", std::process::id()));
+        let path = dir.join("auto-threads.json");
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(load(&path, "a"), None);
+
+        let a = Calibration { threads: 6, hashrate: 1234.5 };
+        let b = Calibration { threads: 12, hashrate: 2345.5 };
+        store(&path, "a".to_string(), a).unwrap();
+        store(&path, "b".to_string(), b).unwrap();
+        assert_eq!(load(&path, "a"), Some(a));
+        assert_eq!(load(&path, "b"), Some(b));
+        assert_eq!(load(&path, "c"), None);
+
+        // A corrupt cache is recalibrated and overwritten
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(load(&path, "a"), None);
+        store(&path, "a".to_string(), a).unwrap();
+        assert_eq!(load(&path, "a"), Some(a));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
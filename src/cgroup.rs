@@ -0,0 +1,213 @@
+use crate::affinity::CpuList;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// The CPU limits of the cgroup we're running in (e.g. `docker run --cpus` or `--cpuset-cpus`)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CpuLimit {
+    /// CPU bandwidth quota, in CPUs
+    pub quota: Option<f64>,
+    /// Amount of CPUs in the cpuset we're allowed to run on
+    pub cpuset: Option<usize>,
+}
+
+impl CpuLimit {
+    pub fn detect() -> Self {
+        // Not being in a cgroup (or not being on Linux) just means there are no limits.
+        let proc_cgroup = fs::read_to_string(PROC_SELF_CGROUP).unwrap_or_default();
+        Self::read(Path::new(CGROUP_ROOT), &proc_cgroup)
+    }
+
+    /// Reads the limits from the cgroup filesystem mounted at `root`,
+    /// `proc_cgroup` is the content of `/proc/self/cgroup` and supports both cgroup v1 and v2.
+    pub fn read(root: &Path, proc_cgroup: &str) -> Self {
+        let mut limit = Self::default();
+        for line in proc_cgroup.lines() {
+            // hierarchy-ID:controller-list:cgroup-path
+            let mut parts = line.splitn(3, ':');
+            let (Some(_), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+            if controllers.is_empty() {
+                // v2 has a single hierarchy with all the controllers in it.
+                let mount = root.to_path_buf();
+                limit.quota = min(limit.quota, lowest_in_hierarchy(&mount, path, read_cpu_max));
+                limit.cpuset = min(limit.cpuset, read_cpuset(&cgroup_dir(&mount, path), &["cpuset.cpus.effective"]));
+                continue;
+            }
+            let mount = root.join(controllers);
+            for controller in controllers.split(',') {
+                match controller {
+                    "cpu" => limit.quota = min(limit.quota, lowest_in_hierarchy(&mount, path, read_cfs_quota)),
+                    "cpuset" => {
+                        let files = ["cpuset.effective_cpus", "cpuset.cpus"];
+                        limit.cpuset = min(limit.cpuset, read_cpuset(&cgroup_dir(&mount, path), &files));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        limit
+    }
+
+    /// Picks the default amount of miner threads, together with the reason for picking it.
+    pub fn default_threads(&self, physical_cores: u16) -> (u16, String) {
+        let mut threads = (physical_cores, format!("{} physical cores", physical_cores));
+        if let Some(cpuset) = self.cpuset {
+            let cpuset_threads = u16::try_from(cpuset).unwrap_or(u16::MAX).max(1);
+            if cpuset_threads < threads.0 {
+                threads = (cpuset_threads, format!("a cgroup cpuset of {} CPUs", cpuset));
+            }
+        }
+        if let Some(quota) = self.quota {
+            let quota_threads = quota.ceil().clamp(1.0, f64::from(u16::MAX)) as u16;
+            if quota_threads < threads.0 {
+                threads = (quota_threads, format!("a cgroup CPU quota of {:.2} CPUs", quota));
+            }
+        }
+        threads
+    }
+
+    /// The most CPUs our threads can keep busy, `None` without limits.
+    pub fn max_cpus(&self) -> Option<usize> {
+        let quota_cpus = self.quota.map(|quota| quota.ceil().max(1.0) as usize);
+        min(self.cpuset, quota_cpus)
+    }
+
+    /// The fraction of the time each of `threads` threads can hash without going over the CPU quota,
+    /// `None` if they can all run at full speed.
+    pub fn cpu_usage(&self, threads: u16) -> Option<f64> {
+        let usage = self.quota? / f64::from(threads.max(1));
+        (usage < 1.0).then_some(usage)
+    }
+}
+
+fn min<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Our cgroup's directory under `mount`.
+/// Without a cgroup namespace the path is the host's one, which in a container is mounted as the root itself.
+fn cgroup_dir(mount: &Path, path: &str) -> PathBuf {
+    let dir = mount.join(path.trim_start_matches('/'));
+    if dir.is_dir() {
+        dir
+    } else {
+        mount.to_path_buf()
+    }
+}
+
+/// A parent's limit applies to all of its children, so the effective limit is the lowest one on the way up.
+fn lowest_in_hierarchy(mount: &Path, path: &str, read: fn(&Path) -> Option<f64>) -> Option<f64> {
+    cgroup_dir(mount, path).ancestors().take_while(|dir| dir.starts_with(mount)).map(read).fold(None, min)
+}
+
+/// cgroup v2 `cpu.max`: `$MAX $PERIOD`, where `$MAX` can be `max`
+fn read_cpu_max(dir: &Path) -> Option<f64> {
+    let cpu_max = fs::read_to_string(dir.join("cpu.max")).ok()?;
+    let mut parts = cpu_max.split_whitespace();
+    let quota: f64 = parts.next()?.parse().ok()?;
+    let period: f64 = parts.next()?.parse().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// cgroup v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us`, where the quota is -1 when unlimited
+fn read_cfs_quota(dir: &Path) -> Option<f64> {
+    let quota: f64 = fs::read_to_string(dir.join("cpu.cfs_quota_us")).ok()?.trim().parse().ok()?;
+    let period: f64 = fs::read_to_string(dir.join("cpu.cfs_period_us")).ok()?.trim().parse().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+fn read_cpuset(dir: &Path, files: &[&str]) -> Option<usize> {
+    files
+        .iter()
+        .find_map(|file| fs::read_to_string(dir.join(file)).ok()?.parse::<CpuList>().ok())
+        .map(|cpus| cpus.0.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cgroup::CpuLimit;
+    use std::{fs, path::PathBuf};
+
+    fn fake_cgroup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("kaspa-miner-cgroup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_cgroup_v2() {
+        // docker run --cpus=0.1 --cpuset-cpus=0-3, with a cgroup namespace
+        let root = fake_cgroup("v2", &[("cpu.max", "10000 100000\n"), ("cpuset.cpus.effective", "0-3\n")]);
+        let limit = CpuLimit::read(&root, "0::/\n");
+        assert_eq!(limit, CpuLimit { quota: Some(0.1), cpuset: Some(4) });
+        assert_eq!(limit.default_threads(16), (1, "a cgroup CPU quota of 0.10 CPUs".to_string()));
+        assert_eq!(limit.cpu_usage(1), Some(0.1));
+        assert_eq!(limit.max_cpus(), Some(1));
+        fs::remove_dir_all(&root).unwrap();
+
+        // A nested cgroup, where the parent has the lower limit
+        let root = fake_cgroup(
+            "v2-nested",
+            &[
+                ("cpu.max", "max 100000\n"),
+                ("miner.slice/cpu.max", "250000 100000\n"),
+                ("system/cpu.max", "50000 100000"),
+            ],
+        );
+        assert_eq!(CpuLimit::read(&root, "0::/miner.slice\n").quota, Some(2.5));
+        fs::write(root.join("cpu.max"), "150000 100000\n").unwrap();
+        assert_eq!(CpuLimit::read(&root, "0::/miner.slice\n").quota, Some(1.5));
+        fs::remove_dir_all(&root).unwrap();
+
+        // Unlimited
+        let root = fake_cgroup("v2-max", &[("cpu.max", "max 100000\n")]);
+        let limit = CpuLimit::read(&root, "0::/\n");
+        assert_eq!(limit, CpuLimit::default());
+        assert_eq!(limit.default_threads(8), (8, "8 physical cores".to_string()));
+        assert_eq!(limit.cpu_usage(8), None);
+        assert_eq!(limit.max_cpus(), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_cgroup_v1() {
+        let root = fake_cgroup(
+            "v1",
+            &[
+                ("cpu,cpuacct/cpu.cfs_quota_us", "250000\n"),
+                ("cpu,cpuacct/cpu.cfs_period_us", "100000\n"),
+                ("cpuset/cpuset.effective_cpus", "0-1\n"),
+            ],
+        );
+        // Without a cgroup namespace we see the host's path, which is mounted as the root
+        let proc_cgroup = "12:cpuset:/docker/abcd\n4:cpu,cpuacct:/docker/abcd\n1:name=systemd:/docker/abcd\n";
+        let limit = CpuLimit::read(&root, proc_cgroup);
+        assert_eq!(limit, CpuLimit { quota: Some(2.5), cpuset: Some(2) });
+        assert_eq!(limit.default_threads(8), (2, "a cgroup cpuset of 2 CPUs".to_string()));
+        assert_eq!(limit.cpu_usage(2), None);
+        assert_eq!(limit.cpu_usage(4), Some(0.625));
+        assert_eq!(limit.max_cpus(), Some(2));
+
+        fs::write(root.join("cpu,cpuacct/cpu.cfs_quota_us"), "-1\n").unwrap();
+        assert_eq!(CpuLimit::read(&root, proc_cgroup).quota, None);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(CpuLimit::read(&root, ""), CpuLimit::default());
+    }
+}
@@ -1,18 +1,27 @@
-use crate::Error;
-use clap::{ArgGroup, Parser};
+use crate::{
+    affinity::{CpuAffinity, CpuList},
+    message_file::MessageFormat,
+    verify::P2pFormat,
+    Error,
+};
+use clap::{ArgGroup, Args, Parser, Subcommand};
 use log::LevelFilter;
-use std::{net::IpAddr, str::FromStr};
+use std::{net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
 
 #[derive(Debug, Parser)]
 #[clap(about, version, author)]
 #[clap(group(ArgGroup::new("required")))]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Opt {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(short, long, display_order = 3)]
     /// Enable debug logging level
     pub debug: bool,
-    #[clap(short = 'a', long = "mining-address", display_order = 0)]
+    #[clap(short = 'a', long = "mining-address", display_order = 0, required = true)]
     /// The Kaspa address for the miner reward
-    pub mining_address: String,
+    pub mining_address: Option<String>,
     #[clap(short = 's', long = "kaspad-address", default_value = "127.0.0.1", display_order = 1)]
     /// The IP of the kaspad instance
     pub kaspad_address: String,
@@ -33,13 +42,13 @@ pub struct Opt {
     /// Use testnet instead of mainnet [default: false]
     testnet: bool,
     #[clap(short = 't', long = "threads", display_order = 5)]
-    /// Amount of miner threads to launch [default: number of logical cpus]
+    /// Amount of miner threads to launch [default: number of physical cores, limited by the container's CPU quota]
     pub num_threads: Option<u16>,
     #[clap(long = "mine-when-not-synced", display_order = 8)]
     /// Mine even when kaspad says it is not synced, only useful when passing `--allow-submit-block-when-not-synced` to kaspad  [default: false]
     pub mine_when_not_synced: bool,
     #[clap(long = "throttle", display_order = 9)]
-    /// Throttle (milliseconds) between each pow hash generation (used for development testing)
+    /// Throttle (milliseconds) between each pow hash generation (used for development testing), see `--cpu-usage` for finer control
     pub throttle: Option<u64>,
     #[clap(long, display_order = 10)]
     /// Output logs in alternative format (same as kaspad)
@@ -47,6 +56,159 @@ pub struct Opt {
     #[clap(long = "user-agent-suffix", display_order = 11)]
     /// Custom user agent suffix (max 20 characters)
     pub user_agent_suffix: Option<String>,
+    #[clap(long = "cpu-affinity", display_order = 12)]
+    /// Pin miner threads to CPUs (Linux only), `auto` for one thread per physical core or a CPU list like `0-3,8` [default: Off]
+    pub cpu_affinity: Option<CpuAffinity>,
+    #[clap(long = "reserved-cpus", display_order = 13, requires = "cpu_affinity")]
+    /// CPUs to keep miner threads off when using `--cpu-affinity`, `auto` also skips their SMT siblings
+    pub reserved_cpus: Option<CpuList>,
+    #[clap(long = "cpu-usage", display_order = 14, value_parser = parse_cpu_usage, conflicts_with = "throttle")]
+    /// Percentage of the time each miner thread spends hashing, e.g. `25` to use a quarter of every core [default: 100, or the container's CPU quota]
+    pub cpu_usage: Option<f64>,
+    #[clap(long = "max-hashrate", display_order = 15, value_parser = parse_max_hashrate, conflicts_with_all = ["throttle", "cpu_usage"])]
+    /// Cap the total hashrate of all the miner threads together (hashes per second)
+    pub max_hashrate: Option<f64>,
+    #[clap(long = "auto-threads", display_order = 16, conflicts_with = "num_threads")]
+    /// Pick the amount of threads with the highest hashrate by benchmarking a few, the result is cached for later runs
+    pub auto_threads: bool,
+    #[clap(long = "shares", display_order = 17, value_parser = clap::value_parser!(u64).range(2..))]
+    /// Count hashes under a target this many times easier than the block's as shares, and report the hashrate they imply next to the counted one [default: Off]
+    pub shares: Option<u64>,
+    #[clap(long = "roll-timestamp", display_order = 18, value_parser = clap::value_parser!(u64).range(1..))]
+    /// Move the header timestamp to the current time every this many seconds while mining the same template, the block template's own timestamp is the minimum [default: Off]
+    pub roll_timestamp: Option<u64>,
+    #[clap(long = "generate", display_order = 19, value_parser = clap::value_parser!(u64).range(1..))]
+    /// Mine until this many blocks are accepted by kaspad, then print their hashes as JSON and exit [default: Off]
+    pub generate: Option<u64>,
+    #[clap(long = "generate-timeout", display_order = 20, requires = "generate")]
+    /// Give up on `--generate` after this many seconds, printing the blocks accepted so far and exiting with code 2
+    pub generate_timeout: Option<u64>,
+    #[clap(long = "block-interval", display_order = 21, value_parser = parse_block_interval, conflicts_with_all = ["throttle", "cpu_usage", "max_hashrate"])]
+    /// Throttle the miner to find a block every this many seconds on average, following the difficulty of every template [default: Off]
+    pub block_interval: Option<Duration>,
+    #[clap(long = "hashrate-interval", display_order = 22, value_parser = clap::value_parser!(u64).range(1..))]
+    /// How often to log the hashrate along with its 1, 5 and 15 minute averages, in seconds [default: 10]
+    pub hashrate_interval: Option<u64>,
+    #[clap(long = "stats-interval", display_order = 23, value_parser = clap::value_parser!(u64).range(1..))]
+    /// How often to log the blocks found against the expected amount, per payout address, in seconds. Also logged on exit [default: 300]
+    pub stats_interval: Option<u64>,
+    #[clap(long = "stats-file", display_order = 24)]
+    /// Where to keep the lifetime stats of all the runs, see the `stats` command [default: $XDG_STATE_HOME/kaspa-miner/stats.json]
+    pub stats_file: Option<PathBuf>,
+    #[clap(long = "archive-dir", display_order = 25)]
+    /// Save every found block to this directory, with its template id, PoW, target, payout address and kaspad's response [default: Off]
+    pub archive_dir: Option<PathBuf>,
+    #[clap(long = "archive-max-blocks", display_order = 26, requires = "archive_dir", value_parser = clap::value_parser!(u64).range(1..))]
+    /// How many blocks to keep in the archive, the oldest ones are deleted first [default: 1000]
+    pub archive_max_blocks: Option<u64>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Measure the hashrate on synthetic block templates, without connecting to kaspad
+    Bench(BenchOpt),
+    /// Print the lifetime stats of all the previous runs
+    Stats(StatsOpt),
+    /// Print the hashes of an RpcBlock from a file and check its PoW, exiting with an error if it isn't valid
+    VerifyBlock(VerifyOpt),
+    /// Print the hashes of an RpcBlockHeader from a file, and whether its PoW is valid
+    HashHeader(VerifyOpt),
+    /// Check the PoW of the blocks in dumps of kaspad's p2p messages, exiting with an error if any isn't valid
+    VerifyP2p(VerifyP2pOpt),
+    /// Mine a block template from a file without kaspad, and write the solved block
+    Solve(SolveOpt),
+    /// Submit solved blocks from files to kaspad, e.g. ones found while it was down
+    Submit(SubmitOpt),
+}
+
+#[derive(Debug, Args)]
+pub struct BenchOpt {
+    #[clap(short = 't', long = "threads")]
+    /// Amount of threads to benchmark [default: number of physical cores]
+    pub num_threads: Option<u16>,
+    #[clap(short, long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    /// How long to run the benchmark for, in seconds
+    pub duration: u64,
+    #[clap(long)]
+    /// Print the results as JSON, for comparing machines and builds
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsOpt {
+    #[clap(long = "stats-file")]
+    /// The stats file to print [default: $XDG_STATE_HOME/kaspa-miner/stats.json]
+    pub stats_file: Option<PathBuf>,
+    #[clap(long)]
+    /// Print the stats as JSON
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyOpt {
+    /// The file to read, or `-` for stdin [default: stdin]
+    pub file: Option<PathBuf>,
+    #[clap(long, value_enum, default_value = "auto")]
+    /// The format of the file
+    pub format: MessageFormat,
+    #[clap(long)]
+    /// Print the hashes as JSON
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyP2pOpt {
+    /// The dumps to read, or `-` for stdin [default: stdin]
+    pub files: Vec<PathBuf>,
+    #[clap(long, value_enum, default_value = "grpc")]
+    /// The format of the dumps
+    pub format: P2pFormat,
+    #[clap(long)]
+    /// Print the hashes as JSON
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SolveOpt {
+    /// A GetBlockTemplateResponse or RpcBlock to mine, or `-` for stdin [default: stdin]
+    pub template: Option<PathBuf>,
+    #[clap(long, value_enum, default_value = "auto")]
+    /// The format of the template file
+    pub format: MessageFormat,
+    #[clap(long, value_parser = parse_bits)]
+    /// Mine against the target of these compact bits instead of the header's, e.g. `0x207fffff` for the easiest target. The block keeps the header's bits, so kaspad only accepts it if its PoW meets them too
+    pub bits: Option<u32>,
+    #[clap(short = 't', long = "threads")]
+    /// Amount of miner threads to launch [default: number of physical cores]
+    pub num_threads: Option<u16>,
+    #[clap(short, long)]
+    /// Where to write the solved RpcBlock, or `-` for stdout [default: stdout]
+    pub output: Option<PathBuf>,
+    #[clap(long)]
+    /// Write the solved block as protobuf instead of JSON
+    pub protobuf: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SubmitOpt {
+    #[clap(required = true)]
+    /// The RpcBlock files to submit, in order
+    pub blocks: Vec<PathBuf>,
+    #[clap(long, value_enum, default_value = "auto")]
+    /// The format of the block files
+    pub format: MessageFormat,
+    #[clap(short = 's', long = "kaspad-address", default_value = "127.0.0.1")]
+    /// The IP of the kaspad instance
+    kaspad_address: String,
+    #[clap(short, long)]
+    /// Kaspad port [default: Mainnet = 16110, Testnet = 16210]
+    port: Option<u16>,
+    #[clap(long)]
+    /// Use testnet instead of mainnet [default: false]
+    testnet: bool,
+    #[clap(long = "allow-non-daa-blocks")]
+    /// Let kaspad accept blocks too old for its difficulty adjustment window, such as ones found long ago
+    pub allow_non_daa_blocks: bool,
 }
 
 fn parse_devfund_percent(s: &str) -> Result<u16, &'static str> {
@@ -72,17 +234,69 @@ fn parse_devfund_percent(s: &str) -> Result<u16, &'static str> {
     Ok(prefix * 100 + postfix)
 }
 
+fn parse_cpu_usage(s: &str) -> Result<f64, &'static str> {
+    let err = "cpu-usage should be a percentage above 0 and up to 100";
+    let percent: f64 = s.trim_end_matches('%').parse().map_err(|_| err)?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(err);
+    }
+    Ok(percent)
+}
+
+fn parse_max_hashrate(s: &str) -> Result<f64, &'static str> {
+    let err = "max-hashrate should be an amount of hashes per second, at least 1";
+    let rate: f64 = s.parse().map_err(|_| err)?;
+    if !(rate >= 1.0 && rate.is_finite()) {
+        return Err(err);
+    }
+    Ok(rate)
+}
+
+fn parse_block_interval(s: &str) -> Result<Duration, &'static str> {
+    let err = "block-interval should be a positive amount of seconds";
+    let seconds: f64 = s.parse().map_err(|_| err)?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(err),
+    }
+}
+
+fn parse_bits(s: &str) -> Result<u32, &'static str> {
+    let err = "bits should be a 32 bit integer, in hex if it starts with 0x";
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).map_err(|_| err),
+        None => s.parse().map_err(|_| err),
+    }
+}
+
+fn default_port(testnet: bool) -> u16 {
+    if testnet {
+        16210
+    } else {
+        16110
+    }
+}
+
+/// The URL of kaspad's gRPC server, `address` is its IP or already a `grpc://` URL.
+fn grpc_address(address: &str, port: u16) -> Result<String, Error> {
+    let address = if address.is_empty() { "127.0.0.1" } else { address };
+    if address.starts_with("grpc://") {
+        return Ok(address.to_string());
+    }
+    IpAddr::from_str(address)?;
+    Ok(format!("grpc://{}:{}", address, port))
+}
+
+impl SubmitOpt {
+    pub fn kaspad_address(&self) -> Result<String, Error> {
+        grpc_address(&self.kaspad_address, self.port.unwrap_or(default_port(self.testnet)))
+    }
+}
+
 impl Opt {
     pub fn process(&mut self) -> Result<(), Error> {
-        if self.kaspad_address.is_empty() {
-            self.kaspad_address = "127.0.0.1".to_string();
-        }
-
-        if !self.kaspad_address.starts_with("grpc://") {
-            IpAddr::from_str(&self.kaspad_address)?;
-            let port = self.port();
-            self.kaspad_address = format!("grpc://{}:{}", self.kaspad_address, port);
-        }
+        let port = self.port();
+        self.kaspad_address = grpc_address(&self.kaspad_address, port)?;
         log::info!("Kaspad address: {}", self.kaspad_address);
 
         if let Some(suffix) = &self.user_agent_suffix {
@@ -98,7 +312,7 @@ impl Opt {
     }
 
     fn port(&mut self) -> u16 {
-        *self.port.get_or_insert(if self.testnet { 16210 } else { 16110 })
+        *self.port.get_or_insert(default_port(self.testnet))
     }
 
     pub fn log_level(&self) -> LevelFilter {
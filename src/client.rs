@@ -1,12 +1,15 @@
 use crate::{
+    archive::Submission,
     miner::MinerManager,
     proto::{
         kaspad_message::Payload, rpc_client::RpcClient, GetBlockTemplateRequestMessage, GetInfoRequestMessage,
         KaspadMessage,
     },
+    submissions::SharedSubmissions,
     Error, ShutdownHandler,
 };
 use log::{error, info, warn};
+use std::{collections::VecDeque, sync::Arc};
 use tokio::sync::mpsc::{self, error::SendError, Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{transport::Channel as TonicChannel, Streaming};
@@ -23,7 +26,10 @@ pub struct KaspadHandler {
     devfund_address: Option<String>,
     devfund_percent: u16,
     block_template_ctr: u64,
+    /// The pay addresses of the requested templates, kaspad responds in the same order
+    requested_pay_addresses: VecDeque<String>,
     extra_data: String,
+    submissions: SharedSubmissions,
 }
 
 impl KaspadHandler {
@@ -32,6 +38,7 @@ impl KaspadHandler {
         miner_address: String,
         mine_when_not_synced: bool,
         user_agent_suffix: Option<String>,
+        submissions: SharedSubmissions,
     ) -> Result<Self, Error>
     where
         D: TryInto<tonic::transport::Endpoint>,
@@ -60,16 +67,19 @@ impl KaspadHandler {
             )
             .await?;
         let stream = client.message_stream(ReceiverStream::new(recv)).await?.into_inner();
+        submissions.lock().unwrap_or_else(|e| e.into_inner()).disconnected();
         Ok(Self {
             client,
             stream,
             send_channel,
-            miner_address,
+            miner_address: miner_address.clone(),
             mine_when_not_synced,
             devfund_address: None,
             devfund_percent: 0,
             block_template_ctr: 0,
+            requested_pay_addresses: VecDeque::from([miner_address.clone()]),
             extra_data,
+            submissions,
         })
     }
 
@@ -90,6 +100,7 @@ impl KaspadHandler {
             _ => self.miner_address.clone(),
         };
         self.block_template_ctr += 1;
+        self.requested_pay_addresses.push_back(pay_address.clone());
         self.client_send(GetBlockTemplateRequestMessage { pay_address, extra_data: self.extra_data.clone() }).await
     }
 
@@ -102,6 +113,9 @@ impl KaspadHandler {
                 Some(payload) => self.handle_message(payload, miner).await?,
                 None => warn!("kaspad message payload is empty"),
             }
+            if self.submissions.lock().unwrap_or_else(|e| e.into_inner()).is_done() {
+                break;
+            }
         }
         Ok(())
     }
@@ -109,17 +123,42 @@ impl KaspadHandler {
     async fn handle_message(&mut self, msg: Payload, miner: &mut MinerManager) -> Result<(), Error> {
         match msg {
             Payload::NewBlockTemplateNotification(_) => self.client_get_block_template().await?,
-            Payload::GetBlockTemplateResponse(template) => match (template.block, template.is_synced, template.error) {
-                (Some(b), true, None) => miner.process_block(Some(b))?,
-                (Some(b), false, None) if self.mine_when_not_synced => miner.process_block(Some(b))?,
-                (_, false, None) => miner.process_block(None)?,
-                (_, _, Some(e)) => warn!("GetTemplate returned with an error: {:?}", e),
-                (None, true, None) => error!("No block and No Error!"),
-            },
-            Payload::SubmitBlockResponse(res) => match res.error {
-                None => info!("Block submitted successfully!"),
-                Some(e) => warn!("Failed submitting block: {:?}", e),
-            },
+            Payload::GetBlockTemplateResponse(template) => {
+                let pay_address =
+                    self.requested_pay_addresses.pop_front().unwrap_or_else(|| self.miner_address.clone());
+                match (template.block, template.is_synced, template.error) {
+                    (Some(b), true, None) => miner.process_block(Some(b), &pay_address),
+                    (Some(b), false, None) if self.mine_when_not_synced => miner.process_block(Some(b), &pay_address),
+                    (_, false, None) => miner.process_block(None, &pay_address),
+                    (_, _, Some(e)) => warn!("GetTemplate returned with an error: {:?}", e),
+                    (None, true, None) => error!("No block and No Error!"),
+                }
+            }
+            Payload::SubmitBlockResponse(res) => {
+                let hash = self.submissions.lock().unwrap_or_else(|e| e.into_inner()).respond(res.error.is_none());
+                miner.stats().block_response(res.error.is_none());
+                if let (Some(archive), Some(hash)) = (miner.archive(), hash) {
+                    let submission = match &res.error {
+                        None => Submission::Accepted,
+                        Some(e) => Submission::Rejected {
+                            reject_reason: res.reject_reason().as_str_name().to_string(),
+                            error: e.message.clone(),
+                        },
+                    };
+                    // The archive reads and rewrites files, which shouldn't hold up the runtime
+                    let archive = Arc::clone(archive);
+                    tokio::task::spawn_blocking(move || {
+                        if let Err(e) = archive.set_submission(hash, submission) {
+                            warn!("Failed archiving the response to block {:x}: {}", hash, e);
+                        }
+                    });
+                }
+                let block = hash.map(|hash| format!(" {:x}", hash)).unwrap_or_default();
+                match res.error {
+                    None => info!("Block{} submitted successfully!", block),
+                    Some(e) => warn!("Failed submitting block{}: {:?}", block, e),
+                }
+            }
             Payload::GetBlockResponse(msg) => {
                 if let Some(e) = msg.error {
                     return Err(e.message.into());
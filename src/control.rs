@@ -0,0 +1,60 @@
+use log::{info, warn};
+use std::{io::BufRead, str::FromStr};
+use tokio::sync::watch;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Change the amount of miner threads
+    Threads(u16),
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["threads", n] => match n.parse() {
+                Ok(n) if n > 0 => Ok(Self::Threads(n)),
+                _ => Err(format!("Invalid amount of threads: {}", n)),
+            },
+            _ => Err(format!("Unknown command: `{}`, available commands: `threads <N>`", s.trim())),
+        }
+    }
+}
+
+/// Reads control commands from stdin, one per line.
+/// This runs on its own thread, as a blocking read from stdin can't be cancelled and would hang the runtime's shutdown.
+pub fn spawn_stdin_control(num_threads: watch::Sender<u16>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(Command::Threads(n)) => {
+                    info!("Setting the amount of miner threads to {}", n);
+                    num_threads.send_replace(n);
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::control::Command;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!("threads 4".parse(), Ok(Command::Threads(4)));
+        assert_eq!("  threads   12 \n".parse(), Ok(Command::Threads(12)));
+        assert!("threads 0".parse::<Command>().is_err());
+        assert!("threads -1".parse::<Command>().is_err());
+        assert!("threads".parse::<Command>().is_err());
+        assert!("thread 4".parse::<Command>().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+/// The windows of the moving averages, like the unix load averages
+const WINDOWS: [Duration; 3] = [Duration::from_secs(60), Duration::from_secs(5 * 60), Duration::from_secs(15 * 60)];
+
+/// Exponentially weighted 1, 5 and 15 minute averages of the hashrate, and the session's total.
+#[derive(Debug, Default, Clone)]
+pub struct HashrateAverages {
+    averages: Option<[f64; 3]>,
+    total_hashes: u64,
+    total_time: Duration,
+}
+
+impl HashrateAverages {
+    /// Adds a sample of `hashes` done over `elapsed`. Samples without hashes decay the averages,
+    /// but only once mining started.
+    pub fn add(&mut self, hashes: u64, elapsed: Duration) {
+        if hashes == 0 && self.averages.is_none() {
+            return;
+        }
+        self.total_hashes += hashes;
+        self.total_time += elapsed;
+        let rate = hashes as f64 / elapsed.as_secs_f64();
+        match &mut self.averages {
+            // The first sample is all we know, rather than decaying up from zero
+            None => self.averages = Some([rate; 3]),
+            Some(averages) => {
+                for (average, window) in averages.iter_mut().zip(WINDOWS) {
+                    let decay = (-elapsed.as_secs_f64() / window.as_secs_f64()).exp();
+                    *average = *average * decay + rate * (1.0 - decay);
+                }
+            }
+        }
+    }
+
+    /// The 1, 5 and 15 minute averages, once there was a sample.
+    pub fn averages(&self) -> Option<[f64; 3]> {
+        self.averages
+    }
+
+    pub fn total_hashes(&self) -> u64 {
+        self.total_hashes
+    }
+
+    /// The average hashrate over the whole session
+    pub fn session_hashrate(&self) -> f64 {
+        if self.total_time.is_zero() {
+            0.0
+        } else {
+            self.total_hashes as f64 / self.total_time.as_secs_f64()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashrate::HashrateAverages;
+    use std::time::Duration;
+
+    #[test]
+    fn test_hashrate_averages() {
+        let mut hashrate = HashrateAverages::default();
+        assert_eq!(hashrate.averages(), None);
+        assert_eq!(hashrate.session_hashrate(), 0.0);
+
+        hashrate.add(10_000, Duration::from_secs(10));
+        assert_eq!(hashrate.averages(), Some([1000.0; 3]));
+
+        // A minute at double the rate gets the 1 minute average most of the way there
+        for _ in 0..6 {
+            hashrate.add(20_000, Duration::from_secs(10));
+        }
+        let [m1, m5, m15] = hashrate.averages().unwrap();
+        assert!((m1 - (2000.0 - 1000.0 / std::f64::consts::E)).abs() < 1e-6);
+        assert!(m1 > m5 && m5 > m15 && m15 > 1000.0);
+
+        assert_eq!(hashrate.total_hashes(), 130_000);
+        assert!((hashrate.session_hashrate() - 130_000.0 / 70.0).abs() < 1e-9);
+
+        // Idle intervals bring the averages down
+        hashrate.add(0, Duration::from_secs(60));
+        let [idle_m1, idle_m5, idle_m15] = hashrate.averages().unwrap();
+        assert!((idle_m1 - m1 / std::f64::consts::E).abs() < 1e-6);
+        assert!(idle_m5 < m5 && idle_m15 < m15);
+        assert!((hashrate.session_hashrate() - 130_000.0 / 130.0).abs() < 1e-9);
+
+        // Before mining starts they aren't counted at all
+        let mut hashrate = HashrateAverages::default();
+        hashrate.add(0, Duration::from_secs(10));
+        assert_eq!(hashrate.averages(), None);
+        hashrate.add(10_000, Duration::from_secs(10));
+        assert_eq!(hashrate.averages(), Some([1000.0; 3]));
+        assert_eq!(hashrate.session_hashrate(), 1000.0);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::Error;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+/// Writes `value` as JSON to a temporary file renamed over `path`, so readers and other miners never see a partial file.
+pub fn write<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Each process has its own temporary file, so two miners writing at once can't interleave in it
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_file::write;
+    use std::fs;
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("kaspa-miner-json-{}", std::process::id()));
+        let path = dir.join("nested").join("file.json");
+        let _ = fs::remove_dir_all(&dir);
+        write(&path, &[1, 2]).unwrap();
+        write(&path, &[3]).unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<u8>>(&fs::read(&path).unwrap()).unwrap(), [3]);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{
-    pow::{self, HeaderHasher},
+    pow::{self, Header, HeaderError},
     proto::{
-        kaspad_message::Payload, GetBlockTemplateRequestMessage, GetInfoRequestMessage, KaspadMessage,
-        NotifyBlockAddedRequestMessage, NotifyNewBlockTemplateRequestMessage, RpcBlock, SubmitBlockRequestMessage,
+        kaspad_message::Payload, BlockHeader, BlockMessage, GetBlockTemplateRequestMessage, GetInfoRequestMessage,
+        KaspadMessage, NotifyBlockAddedRequestMessage, NotifyNewBlockTemplateRequestMessage, RpcBlock, RpcBlockHeader,
+        SubmitBlockRequestMessage,
     },
     Hash,
 };
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt::Display, str::FromStr};
 
 impl KaspadMessage {
     #[must_use]
@@ -20,11 +23,11 @@ impl KaspadMessage {
     }
     #[must_use]
     #[inline(always)]
-    pub fn submit_block(block: RpcBlock) -> Self {
+    pub fn submit_block(block: RpcBlock, allow_non_daa_blocks: bool) -> Self {
         KaspadMessage {
             payload: Some(Payload::SubmitBlockRequest(SubmitBlockRequestMessage {
                 block: Some(block),
-                allow_non_daa_blocks: false,
+                allow_non_daa_blocks,
             })),
         }
     }
@@ -57,11 +60,56 @@ impl From<NotifyNewBlockTemplateRequestMessage> for KaspadMessage {
 }
 
 impl RpcBlock {
-    #[must_use]
     #[inline(always)]
-    pub fn block_hash(&self) -> Option<Hash> {
-        let mut hasher = HeaderHasher::new();
-        pow::serialize_header(&mut hasher, self.header.as_ref()?, false);
-        Some(hasher.finalize())
+    pub fn block_hash(&self) -> Result<Hash, HeaderError> {
+        pow::block_hash(self.header.as_ref().ok_or(HeaderError::Missing)?)
+    }
+}
+
+impl BlockHeader {
+    /// The hash identifying the block of a p2p header.
+    pub fn block_hash(&self) -> Result<Hash, HeaderError> {
+        Ok(Header::try_from(self)?.block_hash())
+    }
+}
+
+impl BlockMessage {
+    pub fn block_hash(&self) -> Result<Hash, HeaderError> {
+        self.header.as_ref().ok_or(HeaderError::Missing)?.block_hash()
+    }
+}
+
+impl TryFrom<&BlockHeader> for RpcBlockHeader {
+    type Error = HeaderError;
+
+    fn try_from(header: &BlockHeader) -> Result<Self, HeaderError> {
+        Ok(Self::from(&Header::try_from(header)?))
+    }
+}
+
+impl TryFrom<&RpcBlockHeader> for BlockHeader {
+    type Error = HeaderError;
+
+    fn try_from(header: &RpcBlockHeader) -> Result<Self, HeaderError> {
+        Ok(Self::from(&Header::try_from(header)?))
+    }
+}
+
+/// Deserializes a 64 bit integer that's either a JSON number or a string, as kaspad writes them.
+pub(crate) fn int_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IntOrString<T> {
+        Int(T),
+        String(String),
+    }
+    match IntOrString::deserialize(deserializer)? {
+        IntOrString::Int(int) => Ok(int),
+        IntOrString::String(string) => string.parse().map_err(de::Error::custom),
     }
 }
@@ -0,0 +1,51 @@
+//! The Kaspa proof of work as the kaspa-miner computes it, for tools that need the same hashes.
+//!
+//! Everything works on the [`RpcBlockHeader`](proto::RpcBlockHeader)s kaspad's RPC returns:
+//! [`pow::block_hash`] identifies a block, [`pow::pre_pow_hash`] is what its kHeavyHash matrix is generated from,
+//! [`pow::calculate_pow`] is its kHeavyHash result, and a block is valid if that isn't above its [`pow::target`].
+//! [`pow::Header`] has the same hashes for a header decoded once, from RPC or p2p messages.
+//!
+//! Without the default `std` feature, the crate is `no_std` and only has the allocation-free [`pow::HeaderView`],
+//! [`pow::HeaderHasher`], [`pow::Matrix`] and [`target`], e.g. for verifying kHeavyHash on embedded devices or in WASM.
+//!
+//! ```
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), kaspa_miner::pow::HeaderError> {
+//! use kaspa_miner::{pow, proto::RpcBlockHeader};
+//!
+//! let header = RpcBlockHeader {
+//!     version: 1,
+//!     hash_merkle_root: "00".repeat(32),
+//!     accepted_id_merkle_root: "00".repeat(32),
+//!     utxo_commitment: "00".repeat(32),
+//!     pruning_point: "00".repeat(32),
+//!     bits: 0x207fffff,
+//!     ..Default::default()
+//! };
+//! let pow = pow::calculate_pow(&header)?;
+//! println!("block {:x} has PoW {:x}, valid: {}", pow::block_hash(&header)?, pow, pow <= pow::target(&header));
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() {}
+//! ```
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
+#[cfg(feature = "std")]
+mod kaspad_messages;
+pub mod pow;
+pub mod target;
+
+/// The messages of kaspad's gRPC protocol
+#[cfg(feature = "std")]
+pub mod proto {
+    #![allow(clippy::derive_partial_eq_without_eq)]
+    tonic::include_proto!("protowire");
+}
+
+#[cfg(feature = "std")]
+pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A 256 bit hash, as a little-endian integer
+pub type Hash = target::Uint256;
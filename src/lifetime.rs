@@ -0,0 +1,170 @@
+use crate::{
+    cli::StatsOpt,
+    json_file,
+    stats::{format_duration, SessionStats, Summary},
+    Error,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+
+/// How often the stats file is written while mining
+const SAVE_RATE: Duration = Duration::from_secs(60);
+
+/// The stats of every run of the miner that used the same stats file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub runs: u64,
+    pub uptime_secs: u64,
+    pub hashes: u64,
+    pub blocks_found: u64,
+    pub blocks_accepted: u64,
+    pub blocks_rejected: u64,
+    /// Blocks found on templates paying to the devfund address
+    pub devfund_blocks: u64,
+}
+
+impl LifetimeStats {
+    /// The stats including the current run
+    pub fn with_session(&self, summary: &Summary, devfund_address: Option<&str>) -> Self {
+        let devfund_blocks =
+            devfund_address.and_then(|address| summary.blocks_per_address.get(address)).copied().unwrap_or_default();
+        Self {
+            runs: self.runs + 1,
+            uptime_secs: self.uptime_secs + summary.elapsed.as_secs(),
+            hashes: self.hashes + summary.hashes,
+            blocks_found: self.blocks_found + summary.blocks,
+            blocks_accepted: self.blocks_accepted + summary.accepted,
+            blocks_rejected: self.blocks_rejected + summary.rejected,
+            devfund_blocks: self.devfund_blocks + devfund_blocks,
+        }
+    }
+}
+
+/// Keeps the stats file up to date with the current run.
+#[derive(Debug)]
+pub struct StatsFile {
+    path: PathBuf,
+    /// The stats of the previous runs
+    previous: LifetimeStats,
+    devfund_address: Option<String>,
+}
+
+impl StatsFile {
+    pub fn load(path: PathBuf, devfund_address: Option<String>) -> Result<Self, Error> {
+        let previous = read(&path)?;
+        Ok(Self { path, previous, devfund_address })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self, stats: &SessionStats) -> Result<(), Error> {
+        let lifetime = self.previous.with_session(&stats.summary(Instant::now()), self.devfund_address.as_deref());
+        json_file::write(&self.path, &lifetime)
+    }
+
+    pub async fn save_periodically(self: Arc<Self>, stats: Arc<SessionStats>) {
+        let mut ticker = tokio::time::interval(SAVE_RATE);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.save(&stats) {
+                warn!("Failed saving the stats to {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/kaspa-miner/stats.json`, or the platform's equivalent.
+pub fn default_path() -> Option<PathBuf> {
+    let state_dir = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")))?;
+    Some(state_dir.join("kaspa-miner").join("stats.json"))
+}
+
+/// Reads the stats file, which doesn't exist before the first run.
+pub fn read(path: &Path) -> Result<LifetimeStats, Error> {
+    match fs::read(path) {
+        Ok(stats) => serde_json::from_slice(&stats)
+            .map_err(|e| format!("Failed parsing the stats file {}: {}", path.display(), e).into()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(LifetimeStats::default()),
+        Err(e) => Err(format!("Failed reading the stats file {}: {}", path.display(), e).into()),
+    }
+}
+
+/// Prints the stats file, for the `stats` subcommand.
+pub fn run(opt: &StatsOpt) -> Result<(), Error> {
+    let path = opt.stats_file.clone().or_else(default_path).ok_or("Couldn't find a directory for the stats file")?;
+    let stats = read(&path)?;
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    println!("Stats file:      {}", path.display());
+    println!("Runs:            {}", stats.runs);
+    println!("Uptime:          {}", format_duration(Duration::from_secs(stats.uptime_secs)));
+    println!("Hashes:          {}", stats.hashes);
+    println!("Blocks found:    {}", stats.blocks_found);
+    println!("Blocks accepted: {}", stats.blocks_accepted);
+    println!("Blocks rejected: {}", stats.blocks_rejected);
+    println!("Devfund blocks:  {}", stats.devfund_blocks);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lifetime::{read, StatsFile},
+        stats::SessionStats,
+    };
+    use std::{fs, time::Instant};
+
+    #[test]
+    fn test_stats_file() {
+        let dir = std::env::temp_dir().join(format!("kaspa-miner-stats-{}", std::process::id()));
+        let path = dir.join("stats.json");
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(read(&path).unwrap().runs, 0);
+
+        for run in 1..=2 {
+            let stats = SessionStats::default();
+            stats.new_template(0, 1000.0, "kaspa:miner");
+            stats.new_template(1, 1000.0, "kaspa:devfund");
+            stats.add_hashes(1500);
+            stats.block_found(0, Instant::now());
+            stats.block_found(1, Instant::now());
+            stats.block_response(true);
+            stats.block_response(false);
+
+            let stats_file = StatsFile::load(path.clone(), Some("kaspa:devfund".to_string())).unwrap();
+            // Saving again replaces the current run's stats rather than adding them twice
+            stats_file.save(&stats).unwrap();
+            stats_file.save(&stats).unwrap();
+            let lifetime = read(&path).unwrap();
+            assert_eq!(lifetime.runs, run);
+            assert_eq!(lifetime.hashes, 1500 * run);
+            assert_eq!(lifetime.blocks_found, 2 * run);
+            assert_eq!((lifetime.blocks_accepted, lifetime.blocks_rejected), (run, run));
+            assert_eq!(lifetime.devfund_blocks, run);
+        }
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::write(&path, "{\"hashes\": 5}").unwrap();
+        assert_eq!(read(&path).unwrap().hashes, 5);
+        fs::write(&path, "not json").unwrap();
+        assert!(read(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,36 +3,56 @@
 use chrono::Local;
 use clap::Parser;
 use log::{info, warn};
-use std::error::Error as StdError;
 use std::{
     io::Write,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
+use tokio::sync::watch;
 
 use crate::{
-    cli::Opt, client::KaspadHandler, miner::MinerManager, proto::NotifyNewBlockTemplateRequestMessage, target::Uint256,
+    archive::Archive,
+    block_rate::BlockRate,
+    cgroup::CpuLimit,
+    cli::{Command, Opt},
+    client::KaspadHandler,
+    lifetime::StatsFile,
+    miner::{get_num_cpus, MinerConfig, MinerManager},
+    proto::NotifyNewBlockTemplateRequestMessage,
+    stats::{SessionStats, STATS_RATE},
+    submissions::Submissions,
+    throttle::{Throttle, TokenBucket},
 };
+pub use kaspa_miner::{pow, proto, target, Error, Hash};
 
+mod affinity;
+mod archive;
+mod bench;
+mod block_rate;
+mod calibration;
+mod cgroup;
 mod cli;
 mod client;
-mod kaspad_messages;
+mod control;
+mod hashrate;
+mod json_file;
+mod lifetime;
+mod message_file;
 mod miner;
-mod pow;
+mod shares;
+mod solve;
+mod stats;
+mod submissions;
+mod submit;
 mod swap_rust;
-mod target;
+mod throttle;
+mod verify;
 
-pub mod proto {
-    #![allow(clippy::derive_partial_eq_without_eq)]
-    tonic::include_proto!("protowire");
-}
-
-pub type Error = Box<dyn StdError + Send + Sync + 'static>;
-
-type Hash = Uint256;
+/// The exit code when `--generate` times out before all the blocks were accepted
+const GENERATE_TIMEOUT_EXIT_CODE: i32 = 2;
 
 #[derive(Debug, Clone)]
 pub struct ShutdownHandler(Arc<AtomicBool>);
@@ -60,7 +80,6 @@ impl Drop for ShutdownOnDrop {
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let mut opt: Opt = Opt::parse();
-    opt.process()?;
 
     let mut builder = env_logger::builder();
     builder.filter_level(opt.log_level()).parse_default_env();
@@ -72,34 +91,197 @@ async fn main() -> Result<(), Error> {
     }
     builder.init();
 
-    let throttle = opt.throttle.map(Duration::from_millis);
+    match &opt.command {
+        Some(Command::Bench(bench_opt)) => return bench::run(bench_opt),
+        Some(Command::Stats(stats_opt)) => return lifetime::run(stats_opt),
+        Some(Command::VerifyBlock(verify_opt)) => return verify::verify_block(verify_opt),
+        Some(Command::HashHeader(verify_opt)) => return verify::hash_header(verify_opt),
+        Some(Command::VerifyP2p(verify_opt)) => return verify::verify_p2p(verify_opt),
+        Some(Command::Solve(solve_opt)) => {
+            // Mines with the same threads, so the same self-test gates it
+            pow::self_test()?;
+            info!("PoW self-test passed");
+            return solve::run(solve_opt).await;
+        }
+        Some(Command::Submit(submit_opt)) => return submit::run(submit_opt).await,
+        None => {}
+    }
+    opt.process()?;
+    pow::self_test()?;
+    info!("PoW self-test passed");
+    let mining_address = opt.mining_address.clone().ok_or("--mining-address is required")?;
+
+    let affinity = match &opt.cpu_affinity {
+        Some(affinity) => {
+            let cpus = affinity.resolve(opt.reserved_cpus.as_ref().unwrap_or(&Default::default()))?;
+            info!("Pinning miner threads to CPUs: {:?}", cpus);
+            Some(cpus)
+        }
+        None => None,
+    };
+    let cpu_limit = CpuLimit::detect();
+    let num_threads = match opt.num_threads {
+        Some(num_threads) => Some(num_threads),
+        None if opt.auto_threads => {
+            let usable_cpus = [cpu_limit.max_cpus(), affinity.as_ref().map(Vec::len)].into_iter().flatten().min();
+            Some(tokio::task::spawn_blocking(move || calibration::auto_threads(usable_cpus)).await?)
+        }
+        // When pinning, default to a thread per CPU we pin to.
+        None => affinity.as_ref().and_then(|cpus| cpus.len().try_into().ok()),
+    };
+    let num_threads = match num_threads {
+        Some(num_threads) => num_threads,
+        None => {
+            let (num_threads, reason) = cpu_limit.default_threads(get_num_cpus(None));
+            info!("Defaulting to {} miner threads because of {}", num_threads, reason);
+            num_threads
+        }
+    };
+    let block_rate = opt.block_interval.map(|interval| Arc::new(BlockRate::new(interval)));
+    let throttle = match (opt.throttle, opt.cpu_usage, opt.max_hashrate) {
+        (Some(millis), _, _) => Some(Throttle::Sleep(Duration::from_millis(millis))),
+        (_, Some(percent), _) if percent < 100.0 => Some(Throttle::CpuUsage(percent / 100.0)),
+        (_, Some(_), _) => None,
+        (_, _, Some(rate)) => Some(Throttle::MaxHashrate(Arc::new(TokenBucket::new(rate)))),
+        (None, None, None) => match &block_rate {
+            Some(block_rate) => Some(block_rate.throttle()),
+            None => cpu_limit.cpu_usage(num_threads).map(|usage| {
+                info!("Limiting miner threads to {:.1}% CPU each to stay within the cgroup CPU quota", usage * 100.0);
+                Throttle::CpuUsage(usage)
+            }),
+        },
+    };
+    let archive = match &opt.archive_dir {
+        Some(dir) => {
+            let max_blocks = opt.archive_max_blocks.map_or(Ok(archive::DEFAULT_MAX_BLOCKS), usize::try_from)?;
+            let archive = Archive::open(dir.clone(), max_blocks)?;
+            info!("Archiving found blocks in {}", archive.dir().display());
+            Some(Arc::new(archive))
+        }
+        None => None,
+    };
+    let config = MinerConfig {
+        throttle,
+        affinity,
+        share_ratio: opt.shares,
+        roll_timestamp: opt.roll_timestamp.map(Duration::from_secs),
+        block_rate,
+        log_interval: opt.hashrate_interval.map(Duration::from_secs),
+        target_bits: None,
+        archive,
+    };
+    let (num_threads, num_threads_rx) = watch::channel(num_threads);
+    control::spawn_stdin_control(num_threads);
     let shutdown = ShutdownHandler(Arc::new(AtomicBool::new(false)));
     let _shutdown_when_dropped = shutdown.arm();
+    let submissions = match opt.generate {
+        Some(num_blocks) => Submissions::with_limit(num_blocks.try_into()?),
+        None => Submissions::default(),
+    };
+    let submissions = Arc::new(Mutex::new(submissions));
+    let is_done = || submissions.lock().unwrap_or_else(|e| e.into_inner()).is_done();
+    let stats = Arc::new(SessionStats::default());
+    let stats_interval = opt.stats_interval.map_or(STATS_RATE, Duration::from_secs);
+    let stats_logger = tokio::spawn(Arc::clone(&stats).log_summaries(stats_interval));
+    let stats_file = match (opt.stats_file.clone(), lifetime::default_path()) {
+        (Some(path), _) => Some(Arc::new(StatsFile::load(path, opt.devfund_address.clone())?)),
+        // A broken default file shouldn't stop mining, and is left as is rather than overwritten
+        (None, Some(path)) => match StatsFile::load(path.clone(), opt.devfund_address.clone()) {
+            Ok(stats_file) => Some(Arc::new(stats_file)),
+            Err(e) => {
+                warn!("Not keeping the lifetime stats, failed loading {}: {}", path.display(), e);
+                None
+            }
+        },
+        (None, None) => {
+            warn!("Couldn't find a directory for the stats file, pass `--stats-file` to keep the lifetime stats");
+            None
+        }
+    };
+    let stats_saver =
+        stats_file.clone().map(|stats_file| tokio::spawn(stats_file.save_periodically(Arc::clone(&stats))));
 
-    while !shutdown.is_shutdown() {
-        let mut client = KaspadHandler::connect(
-            opt.kaspad_address.clone(),
-            opt.mining_address.clone(),
-            opt.mine_when_not_synced,
-            opt.user_agent_suffix.clone(),
-        )
-        .await?;
-        if let Some(devfund_address) = &opt.devfund_address {
-            client.add_devfund(devfund_address.clone(), opt.devfund_percent);
-            info!(
-                "devfund enabled, mining {}.{}% of the time to devfund address: {} ",
-                opt.devfund_percent / 100,
-                opt.devfund_percent % 100,
-                devfund_address
+    let mining = async {
+        while !shutdown.is_shutdown() && !is_done() {
+            let mut client = KaspadHandler::connect(
+                opt.kaspad_address.clone(),
+                mining_address.clone(),
+                opt.mine_when_not_synced,
+                opt.user_agent_suffix.clone(),
+                submissions.clone(),
+            )
+            .await?;
+            if let Some(devfund_address) = &opt.devfund_address {
+                client.add_devfund(devfund_address.clone(), opt.devfund_percent);
+                info!(
+                    "devfund enabled, mining {}.{}% of the time to devfund address: {} ",
+                    opt.devfund_percent / 100,
+                    opt.devfund_percent % 100,
+                    devfund_address
+                );
+            }
+            client.client_send(NotifyNewBlockTemplateRequestMessage {}).await?;
+            client.client_get_block_template().await?;
+
+            let mut miner_manager = MinerManager::new(
+                client.send_channel.clone(),
+                submissions.clone(),
+                Arc::clone(&stats),
+                num_threads_rx.clone(),
+                config.clone(),
+                shutdown.clone(),
             );
+            client.listen(&mut miner_manager, shutdown.clone()).await?;
+            if !is_done() {
+                warn!("Disconnected from kaspad, retrying");
+            }
+        }
+        Ok::<_, Error>(())
+    };
+    let mining = async {
+        tokio::select! {
+            result = mining => result,
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutting down");
+                Ok(())
+            }
         }
-        client.client_send(NotifyNewBlockTemplateRequestMessage {}).await?;
-        client.client_get_block_template().await?;
+    };
 
-        let mut miner_manager =
-            MinerManager::new(client.send_channel.clone(), opt.num_threads, throttle, shutdown.clone());
-        client.listen(&mut miner_manager, shutdown.clone()).await?;
-        warn!("Disconnected from kaspad, retrying");
+    let finished = match opt.generate_timeout {
+        Some(timeout) => tokio::time::timeout(Duration::from_secs(timeout), mining).await.ok(),
+        None => Some(mining.await),
+    };
+    stats_logger.abort();
+    stats.log_summary();
+    if let (Some(stats_file), Some(stats_saver)) = (&stats_file, stats_saver) {
+        stats_saver.abort();
+        if let Err(e) = stats_file.save(&stats) {
+            warn!("Failed saving the stats to {}: {}", stats_file.path().display(), e);
+        }
+    }
+    if opt.generate.is_none() {
+        return finished.expect("Only --generate can time out");
+    }
+    let accepted: Vec<_> = submissions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .accepted()
+        .iter()
+        .map(|hash| format!("{:x}", hash))
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&accepted)?);
+    match finished {
+        Some(Ok(())) if !is_done() => Err(format!(
+            "Stopped after {} of {} blocks were accepted",
+            accepted.len(),
+            opt.generate.unwrap_or_default()
+        )
+        .into()),
+        Some(result) => result,
+        None => {
+            warn!("Timed out after {} of {} blocks were accepted", accepted.len(), opt.generate.unwrap_or_default());
+            std::process::exit(GENERATE_TIMEOUT_EXIT_CODE);
+        }
     }
-    Ok(())
 }
@@ -0,0 +1,113 @@
+use crate::Error;
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// How a kaspad message is stored in a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// JSON if the file starts with `{`, protobuf otherwise
+    Auto,
+    /// kaspad's JSON, with its camelCase field names
+    Json,
+    /// The protobuf encoding of the message
+    Protobuf,
+}
+
+/// Reads a message from the file, or from stdin if it's `None` or `-`.
+pub fn read<M: prost::Message + DeserializeOwned + Default>(
+    path: Option<&Path>,
+    format: MessageFormat,
+) -> Result<M, Error> {
+    decode(&read_file(path)?, format).map_err(|e| format!("Failed parsing {}: {}", name(path), e).into())
+}
+
+/// Reads the file, or stdin if it's `None` or `-`.
+pub fn read_file(path: Option<&Path>) -> Result<Vec<u8>, Error> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            Ok(fs::read(path).map_err(|e| format!("Failed reading {}: {}", path.display(), e))?)
+        }
+        _ => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// The file's name for errors
+pub fn name(path: Option<&Path>) -> String {
+    match path {
+        Some(path) if path != Path::new("-") => path.display().to_string(),
+        _ => "stdin".to_string(),
+    }
+}
+
+/// Writes a message as JSON or protobuf to the file, or to stdout if it's `None` or `-`.
+pub fn write<M: prost::Message + Serialize>(path: Option<&Path>, protobuf: bool, message: &M) -> Result<(), Error> {
+    let bytes = if protobuf {
+        message.encode_to_vec()
+    } else {
+        let mut json = serde_json::to_vec_pretty(message)?;
+        json.push(b'\n');
+        json
+    };
+    match path {
+        Some(path) if path != Path::new("-") => {
+            Ok(fs::write(path, bytes).map_err(|e| format!("Failed writing {}: {}", path.display(), e))?)
+        }
+        _ => Ok(io::stdout().write_all(&bytes)?),
+    }
+}
+
+pub fn decode<M: prost::Message + DeserializeOwned + Default>(bytes: &[u8], format: MessageFormat) -> Result<M, Error> {
+    let format = match format {
+        MessageFormat::Auto if bytes.trim_ascii_start().starts_with(b"{") => MessageFormat::Json,
+        MessageFormat::Auto => MessageFormat::Protobuf,
+        format => format,
+    };
+    match format {
+        MessageFormat::Json => Ok(serde_json::from_slice(bytes)?),
+        _ => Ok(M::decode(bytes)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        message_file::{decode, MessageFormat},
+        proto::{RpcBlock, RpcBlockHeader, RpcBlockLevelParents},
+    };
+    use prost::Message;
+
+    #[test]
+    fn test_decode() {
+        let block = RpcBlock {
+            header: Some(RpcBlockHeader {
+                version: 1,
+                parents: vec![RpcBlockLevelParents { parent_hashes: vec!["11".repeat(32)] }],
+                timestamp: 1650000000000,
+                nonce: u64::MAX,
+                daa_score: 7,
+                blue_work: "1abc".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let json = serde_json::to_vec(&block).unwrap();
+        assert_eq!(decode::<RpcBlock>(&json, MessageFormat::Auto).unwrap(), block);
+        assert_eq!(decode::<RpcBlock>(&block.encode_to_vec(), MessageFormat::Auto).unwrap(), block);
+        assert!(decode::<RpcBlock>(&json, MessageFormat::Protobuf).is_err());
+
+        // kaspad's JSON, with missing fields and 64 bit integers as strings
+        let json = r#" {"header": {"version": 1, "parents": [{"parentHashes": ["1111111111111111111111111111111111111111111111111111111111111111"]}],
+            "timestamp": "1650000000000", "nonce": "18446744073709551615", "daaScore": 7, "blueWork": "1abc"}}"#;
+        assert_eq!(decode::<RpcBlock>(json.as_bytes(), MessageFormat::Auto).unwrap(), block);
+        assert!(decode::<RpcBlockHeader>(br#"{"nonce": "-1"}"#, MessageFormat::Json).is_err());
+    }
+}
@@ -1,41 +1,105 @@
 use crate::{
-    pow,
+    affinity,
+    archive::{Archive, ArchivedBlock, Submission},
+    block_rate::BlockRate,
+    pow::{self, Header},
     proto::{KaspadMessage, RpcBlock},
+    shares::{ShareCheck, ShareCounter},
+    stats::SessionStats,
+    submissions::{self, SharedSubmissions},
     swap_rust::WatchSwap,
+    target::{self, Uint256},
+    throttle::{Throttle, Throttler},
     Error, ShutdownHandler,
 };
-use log::{info, warn};
+use log::{debug, error, info, warn};
 use rand::{thread_rng, RngCore};
 use std::{
     num::Wrapping,
     sync::{
-        atomic::{AtomicU64, AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    sync::mpsc::Sender,
+    sync::{mpsc::Sender, watch},
     task::{self, JoinHandle},
     time::MissedTickBehavior,
 };
 
 type MinerHandler = std::thread::JoinHandle<Result<(), Error>>;
 
+/// A running miner thread, which retires at its next checkpoint once this is dropped
+struct MinerThread {
+    handle: MinerHandler,
+    retired: Arc<AtomicBool>,
+    block_channel: WatchSwap<pow::State>,
+}
+
+impl Drop for MinerThread {
+    fn drop(&mut self) {
+        self.retired.store(true, Ordering::Release);
+        // A thread waiting for a template wouldn't reach its checkpoint otherwise
+        self.block_channel.wake_up_threads();
+    }
+}
+
+/// How the miner threads mine, which doesn't change over a session
+#[derive(Debug, Clone, Default)]
+pub struct MinerConfig {
+    pub throttle: Option<Throttle>,
+    /// The CPUs to pin the threads to, round robin
+    pub affinity: Option<Vec<usize>>,
+    /// Count hashes under a target this many times easier than the block's as shares
+    pub share_ratio: Option<u64>,
+    /// How often to move the header timestamp to the current time while mining the same template
+    pub roll_timestamp: Option<Duration>,
+    /// Adjusts the throttle to find blocks at an interval
+    pub block_rate: Option<Arc<BlockRate>>,
+    /// How often to log the hashrate, [`LOG_RATE`] by default
+    pub log_interval: Option<Duration>,
+    /// Mines against the target of these compact bits instead of each template's, the blocks keep the template's bits
+    pub target_bits: Option<u32>,
+    /// Saves every found block with its template and what happened to it
+    pub archive: Option<Arc<Archive>>,
+}
+
+/// Everything needed to launch another miner thread at any time
+#[derive(Clone)]
+struct MinerContext {
+    send_channel: Sender<KaspadMessage>,
+    submissions: SharedSubmissions,
+    send_order: Arc<Mutex<()>>,
+    stats: Arc<SessionStats>,
+    block_channel: WatchSwap<pow::State>,
+    hashes_tried: Arc<AtomicU64>,
+    shares: Option<Arc<ShareCounter>>,
+    config: MinerConfig,
+    shutdown: ShutdownHandler,
+}
+
 #[allow(dead_code)]
 pub struct MinerManager {
-    handles: Vec<MinerHandler>,
     block_channel: WatchSwap<pow::State>,
     send_channel: Sender<KaspadMessage>,
     logger_handle: JoinHandle<()>,
-    is_synced: bool,
+    threads_handle: JoinHandle<()>,
+    is_synced: Arc<AtomicBool>,
     hashes_tried: Arc<AtomicU64>,
-    current_state_id: AtomicUsize,
+    shares: Option<Arc<ShareCounter>>,
+    share_ratio: Option<u64>,
+    target_bits: Option<u32>,
+    block_rate: Option<Arc<BlockRate>>,
+    stats: Arc<SessionStats>,
+    archive: Option<Arc<Archive>>,
 }
 
 impl Drop for MinerManager {
     fn drop(&mut self) {
         self.logger_handle.abort();
+        // This drops the threads' handles, retiring them
+        self.threads_handle.abort();
     }
 }
 
@@ -45,148 +109,421 @@ pub fn get_num_cpus(n_cpus: Option<u16>) -> u16 {
     })
 }
 
-const LOG_RATE: Duration = Duration::from_secs(10);
+/// How often the hashrate is logged by default
+pub const LOG_RATE: Duration = Duration::from_secs(10);
+/// How often threads that exited on their own are relaunched
+const REAP_RATE: Duration = Duration::from_secs(1);
+
+/// The current time the way block headers have it, in milliseconds since the unix epoch
+fn unix_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_millis() as i64)
+}
 
 impl MinerManager {
+    /// Launches `num_threads` miner threads, adding or retiring threads whenever it changes.
     pub fn new(
         send_channel: Sender<KaspadMessage>,
-        n_cpus: Option<u16>,
-        throttle: Option<Duration>,
+        submissions: SharedSubmissions,
+        stats: Arc<SessionStats>,
+        num_threads: watch::Receiver<u16>,
+        config: MinerConfig,
         shutdown: ShutdownHandler,
     ) -> Self {
         let hashes_tried = Arc::new(AtomicU64::new(0));
+        let shares = config.share_ratio.map(|_| Arc::new(ShareCounter::default()));
+        let share_ratio = config.share_ratio;
+        let target_bits = config.target_bits;
+        let block_rate = config.block_rate.clone();
+        let archive = config.archive.clone();
+        let is_synced = Arc::new(AtomicBool::new(true));
+        let log_interval = config.log_interval.unwrap_or(LOG_RATE);
         let watch = WatchSwap::empty();
-        let handles = Self::launch_cpu_threads(
-            send_channel.clone(),
-            hashes_tried.clone(),
-            watch.clone(),
+        let context = MinerContext {
+            send_channel: send_channel.clone(),
+            submissions,
+            send_order: Arc::default(),
+            stats: Arc::clone(&stats),
+            block_channel: watch.clone(),
+            hashes_tried: hashes_tried.clone(),
+            shares: shares.clone(),
+            config,
             shutdown,
-            n_cpus,
-            throttle,
-        )
-        .collect();
+        };
 
         Self {
-            handles,
             block_channel: watch,
             send_channel,
-            logger_handle: task::spawn(Self::log_hashrate(Arc::clone(&hashes_tried))),
-            is_synced: true,
+            logger_handle: task::spawn(Self::log_hashrate(
+                Arc::clone(&hashes_tried),
+                Arc::clone(&is_synced),
+                Arc::clone(&stats),
+                log_interval,
+                shares.clone(),
+                block_rate.clone(),
+            )),
+            threads_handle: task::spawn(Self::manage_cpu_threads(context, num_threads)),
+            is_synced,
             hashes_tried,
-            current_state_id: AtomicUsize::new(0),
+            shares,
+            share_ratio,
+            target_bits,
+            block_rate,
+            stats,
+            archive,
         }
     }
 
-    fn launch_cpu_threads(
-        send_channel: Sender<KaspadMessage>,
-        hashes_tried: Arc<AtomicU64>,
-        work_channel: WatchSwap<pow::State>,
-        shutdown: ShutdownHandler,
-        n_cpus: Option<u16>,
-        throttle: Option<Duration>,
-    ) -> impl Iterator<Item = MinerHandler> {
-        let n_cpus = get_num_cpus(n_cpus);
-        info!("Launching: {} cpu miners", n_cpus);
-        (0..n_cpus).map(move |_| {
-            Self::launch_cpu_miner(
-                send_channel.clone(),
-                work_channel.clone(),
-                hashes_tried.clone(),
-                throttle,
-                shutdown.clone(),
-            )
-        })
+    async fn manage_cpu_threads(context: MinerContext, mut num_threads: watch::Receiver<u16>) {
+        let mut threads: Vec<MinerThread> = Vec::new();
+        let mut reap = tokio::time::interval(REAP_RATE);
+        reap.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (mut fixed, mut launched) = (false, false);
+        loop {
+            let n_cpus = usize::from(*num_threads.borrow_and_update());
+            if !launched {
+                info!("Launching: {} cpu miners", n_cpus);
+                launched = true;
+            } else if n_cpus != threads.len() {
+                info!("Changing the amount of cpu miners from {} to {}", threads.len(), n_cpus);
+            }
+            threads.truncate(n_cpus);
+            // Threads can exit on their own if they fail to submit a block
+            if !context.shutdown.is_shutdown() {
+                for (i, thread) in threads.iter_mut().enumerate() {
+                    if thread.handle.is_finished() {
+                        warn!("Miner thread {} exited, relaunching it", i);
+                        *thread = Self::launch_cpu_miner(i, context.clone());
+                    }
+                }
+            }
+            let running = threads.len();
+            threads.extend((running..n_cpus).map(|i| Self::launch_cpu_miner(i, context.clone())));
+
+            tokio::select! {
+                // Once nothing can change the amount of threads anymore, the current ones are only kept running
+                changed = num_threads.changed(), if !fixed => fixed = changed.is_err(),
+                _ = reap.tick() => {}
+            }
+        }
     }
 
-    pub fn process_block(&mut self, block: Option<RpcBlock>) -> Result<(), Error> {
+    pub fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
+
+    pub fn archive(&self) -> Option<&Arc<Archive>> {
+        self.archive.as_ref()
+    }
+
+    /// Mines `block`, paying to `pay_address`, or stops mining if there's no block because kaspad isn't synced.
+    /// A template whose header can't be hashed is skipped, mining the previous one until kaspad sends another.
+    pub fn process_block(&mut self, block: Option<RpcBlock>, pay_address: &str) {
         let state = if let Some(b) = block {
-            self.is_synced = true;
-            // Relaxed ordering here means there's no promise that the counter will always go up, but the id will always be unique
-            let id = self.current_state_id.fetch_add(1, Ordering::Relaxed);
-            Some(pow::State::new(id, b)?)
+            self.is_synced.store(true, Ordering::Relaxed);
+            let id = self.stats.next_template_id();
+            let mut state = match pow::State::new(id, b) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!("Skipping an invalid block template: {}", e);
+                    return;
+                }
+            };
+            if let Some(bits) = self.target_bits {
+                state.set_target(target::u256_from_compact_target(bits));
+            }
+            if let (Some(ratio), Some(shares)) = (self.share_ratio, &self.shares) {
+                state.set_share_ratio(ratio);
+                shares.set_share_work(state.share_work());
+            }
+            if let Some(block_rate) = &self.block_rate {
+                block_rate.set_block_work(state.block_work());
+            }
+            self.stats.new_template(id, state.block_work(), pay_address);
+            Some(state)
         } else {
-            if !self.is_synced {
-                return Ok(());
+            if !self.is_synced.swap(false, Ordering::Relaxed) {
+                return;
             }
-            self.is_synced = false;
             warn!("Kaspad is not synced, skipping current template");
             None
         };
 
         self.block_channel.swap(state);
-        Ok(())
     }
 
-    pub fn launch_cpu_miner(
-        send_channel: Sender<KaspadMessage>,
-        mut block_channel: WatchSwap<pow::State>,
-        hashes_tried: Arc<AtomicU64>,
-        throttle: Option<Duration>,
-        shutdown: ShutdownHandler,
-    ) -> MinerHandler {
+    fn launch_cpu_miner(index: usize, context: MinerContext) -> MinerThread {
         // We mark it cold as the function is not called often, and it's not in the hot path
         #[cold]
-        fn found_block(send_channel: &Sender<KaspadMessage>, block: RpcBlock) -> Result<(), Error> {
-            let block_hash = block.block_hash().expect("We just got it from the state, we should be able to hash it");
-            send_channel.blocking_send(KaspadMessage::submit_block(block))?;
-            info!("Found a block: {:x}", block_hash);
+        fn found_block(
+            context: &MinerContext,
+            template_id: usize,
+            block: RpcBlock,
+            mined_pow: Uint256,
+        ) -> Result<(), Error> {
+            let MinerContext { send_channel, submissions, send_order, stats, config, .. } = context;
+            let header =
+                match Header::try_from(block.header.as_ref().expect("We checked that a header exists on creation")) {
+                    Ok(header) => header,
+                    Err(e) => {
+                        error!("Can't hash a found block, even though its template was checked: {}", e);
+                        return Ok(());
+                    }
+                };
+            let block_hash = header.block_hash();
+            let (pow, target) = pow::reference_pow(&header);
+            // A block mined against another target than its bits' is only checked against the one it was mined for
+            let target = config.target_bits.map_or(target, target::u256_from_compact_target);
+            if pow != mined_pow {
+                error!(
+                    "Hardware or implementation fault: block {:x} was mined with PoW {:x}, but recomputing it gives {:x}",
+                    block_hash, mined_pow, pow
+                );
+            }
+            let archive = config.archive.as_deref();
+            let archive_block = |submission: Submission| {
+                let Some(archive) = archive else {
+                    return;
+                };
+                let pay_address = stats.pay_address(template_id);
+                let mut archived =
+                    ArchivedBlock::new(block.clone(), block_hash, template_id, pay_address, pow, target, unix_millis());
+                archived.submission = submission;
+                if let Err(e) = archive.add(&archived) {
+                    warn!("Failed archiving block {:x} in {}: {}", block_hash, archive.dir().display(), e);
+                }
+            };
+            let not_submitted = |reason: String| {
+                if let Some(archive) = archive {
+                    if let Err(e) = archive.set_submission(block_hash, Submission::NotSubmitted { reason }) {
+                        warn!("Failed archiving block {:x} in {}: {}", block_hash, archive.dir().display(), e);
+                    }
+                }
+            };
+            if pow > target {
+                warn!("Not submitting block {:x}, its PoW {:x} is above the target {:x}", block_hash, pow, target);
+                archive_block(Submission::NotSubmitted { reason: "The PoW is above the target".to_string() });
+                return Ok(());
+            }
+            if let Some(block_rate) = &config.block_rate {
+                if !block_rate.claim(Instant::now()) {
+                    debug!("Found block {:x}, but it's not time for the next block yet", block_hash);
+                    return Ok(());
+                }
+            }
+            // Archived as pending before it's submitted, so kaspad's response always finds it
+            archive_block(Submission::Pending);
+            match submissions::submit(submissions, send_order, send_channel, block, block_hash) {
+                Ok(true) => {
+                    info!("Found a block: {:x}", block_hash);
+                    let now = Instant::now();
+                    if let Some(block_rate) = &config.block_rate {
+                        block_rate.block_found(now);
+                    }
+                    stats.block_found(template_id, now);
+                }
+                Ok(false) => {
+                    debug!("Found block {:x}, but enough blocks were already submitted", block_hash);
+                    not_submitted("Enough blocks were already submitted".to_string());
+                }
+                Err(e) => {
+                    not_submitted(format!("Failed submitting it: {}", e));
+                    return Err(e);
+                }
+            }
             Ok(())
         }
 
+        let MinerConfig { throttle, affinity, roll_timestamp, .. } = context.config.clone();
+        let cpu = affinity.map(|cpus| cpus[index % cpus.len()]);
+        let retired = Arc::new(AtomicBool::new(false));
+        let is_retired = Arc::clone(&retired);
+        let mut block_channel = context.block_channel.clone();
+        let thread_channel = context.block_channel.clone();
         let mut nonce = Wrapping(thread_rng().next_u64());
-        std::thread::spawn(move || {
+        let handle = std::thread::spawn(move || {
+            let MinerContext { hashes_tried, shares, stats, shutdown, .. } = &context;
+            if let Some(cpu) = cpu {
+                match affinity::pin_current_thread(cpu) {
+                    Ok(()) => debug!("Pinned miner thread to CPU {}", cpu),
+                    Err(e) => warn!("Failed pinning miner thread to CPU {}: {}", cpu, e),
+                }
+            }
+            let mut throttler = throttle.map(Throttler::new);
+            // When the current template was received, and when its timestamp was last rolled
+            let (mut template_start, mut last_roll) = (Instant::now(), Instant::now());
             let mut state = None;
             loop {
                 if state.is_none() {
-                    state = block_channel.wait_for_change().as_deref().cloned();
+                    match block_channel.wait_for_change_or(|| is_retired.load(Ordering::Acquire)) {
+                        Some(new_state) => {
+                            state = new_state.as_deref().cloned();
+                            (template_start, last_roll) = (Instant::now(), Instant::now());
+                        }
+                        None => {
+                            debug!("Retiring miner thread {}", index);
+                            return Ok(());
+                        }
+                    }
                 }
                 let Some(state_ref) = state.as_mut() else {
                     continue;
                 };
                 state_ref.nonce = nonce.0;
 
-                if let Some(block) = state_ref.generate_block_if_pow() {
-                    found_block(&send_channel, block)?;
+                if let Some(pow) = state_ref.check_share() {
+                    if let Some(shares) = shares {
+                        shares.add();
+                    }
+                    if state_ref.is_block(pow) {
+                        found_block(&context, state_ref.id, state_ref.generate_block(), pow)?;
+                    }
                 }
                 nonce += Wrapping(1);
 
                 if nonce.0.is_multiple_of(128) {
                     hashes_tried.fetch_add(128, Ordering::Relaxed);
+                    stats.add_hashes(128);
+                    // Throttled first, so the checks below are fresh after sleeping
+                    if let Some(throttler) = &mut throttler {
+                        throttler.checkpoint(128, || shutdown.is_shutdown() || is_retired.load(Ordering::Acquire));
+                    }
                     if shutdown.is_shutdown() {
                         return Ok(());
                     }
+                    if is_retired.load(Ordering::Acquire) {
+                        debug!("Retiring miner thread {}", index);
+                        return Ok(());
+                    }
                     if let Some(new_state) = block_channel.get_changed() {
                         state = new_state.as_deref().cloned();
+                        (template_start, last_roll) = (Instant::now(), Instant::now());
+                    }
+                    if let (Some(interval), Some(state)) = (roll_timestamp, &mut state) {
+                        if last_roll.elapsed() >= interval {
+                            state.roll_timestamp(unix_millis(), template_start.elapsed());
+                            last_roll = Instant::now();
+                        }
                     }
                 }
 
-                if let Some(sleep_duration) = throttle {
-                    std::thread::sleep(sleep_duration)
+                if let Some(throttler) = &mut throttler {
+                    throttler.after_hash();
                 }
             }
-        })
+        });
+        MinerThread { handle, retired, block_channel: thread_channel }
     }
 
-    async fn log_hashrate(hashes_tried: Arc<AtomicU64>) {
-        let mut ticker = tokio::time::interval(LOG_RATE);
+    async fn log_hashrate(
+        hashes_tried: Arc<AtomicU64>,
+        is_synced: Arc<AtomicBool>,
+        stats: Arc<SessionStats>,
+        interval: Duration,
+        shares: Option<Arc<ShareCounter>>,
+        block_rate: Option<Arc<BlockRate>>,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
         ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
         let mut last_instant = ticker.tick().await;
-        for i in 0u64.. {
+        let mut share_check = ShareCheck::default();
+        let mut blocks_reported = 0;
+        loop {
             let now = ticker.tick().await;
             let hashes = hashes_tried.swap(0, Ordering::Relaxed);
-            let rate = (hashes as f64) / (now - last_instant).as_secs_f64();
-            if hashes == 0 && i % 2 == 0 {
+            let elapsed = now - last_instant;
+            last_instant = now;
+            if !is_synced.load(Ordering::Relaxed) {
                 warn!("Kaspad is still not synced");
-            } else if hashes != 0 {
-                let (rate, suffix) = Self::hash_suffix(rate);
-                info!("Current hashrate is: {:.2} {}", rate, suffix);
             }
-            last_instant = now;
+            // The averages are kept for the whole session, so they carry over reconnections
+            let hashrate = stats.add_hashrate(hashes, elapsed);
+            if hashes == 0 {
+                continue;
+            }
+            let rate = hashes as f64 / elapsed.as_secs_f64();
+            let averages = match hashrate.averages() {
+                Some([m1, m5, m15]) => format!(
+                    " (1m: {}, 5m: {}, 15m: {}), session: {} over {} hashes",
+                    Self::format_hashrate(m1),
+                    Self::format_hashrate(m5),
+                    Self::format_hashrate(m15),
+                    Self::format_hashrate(hashrate.session_hashrate()),
+                    hashrate.total_hashes()
+                ),
+                None => String::new(),
+            };
+            match &shares {
+                Some(shares) => {
+                    let (found, share_work) = shares.take();
+                    share_check.add(hashes, found, share_work);
+                    let effective = found as f64 * share_work / elapsed.as_secs_f64();
+                    info!(
+                        "Current hashrate is: {}, effective: {} ({} shares){}",
+                        Self::format_hashrate(rate),
+                        Self::format_hashrate(effective),
+                        found,
+                        averages
+                    );
+                    Self::check_shares(&share_check);
+                }
+                None => info!("Current hashrate is: {}{}", Self::format_hashrate(rate), averages),
+            }
+            if let Some(block_rate) = &block_rate {
+                Self::report_block_rate(block_rate, rate, &mut blocks_reported);
+            }
         }
     }
 
+    /// Corrects the block rate from the blocks found so far, and compares their interval with the target one
+    /// whenever more blocks were found.
+    fn report_block_rate(block_rate: &BlockRate, hashrate: f64, blocks_reported: &mut u64) {
+        let target = block_rate.interval().as_secs_f64();
+        let needed = block_rate.hashrate();
+        if block_rate.correct(hashrate) {
+            warn!(
+                "A block every {:.1}s needs {} at the current difficulty, blocks will be found slower than that",
+                target,
+                Self::format_hashrate(needed)
+            );
+        }
+        if let (Some(actual), found) = block_rate.actual_interval() {
+            if found > *blocks_reported {
+                *blocks_reported = found;
+                info!(
+                    "Found a block every {:.1}s on average ({} blocks), the target is every {:.1}s, now hashing at {}",
+                    actual.as_secs_f64(),
+                    found,
+                    target,
+                    Self::format_hashrate(block_rate.hashrate())
+                );
+            }
+        }
+    }
+
+    /// Shares are only found by valid hashes, so too few of them means some of the hashes we count are garbage.
+    fn check_shares(share_check: &ShareCheck) {
+        /// Standard deviations past which it's very unlikely to be bad luck
+        const MAX_DEVIATION: f64 = 4.0;
+        if let Some(deviation) = share_check.deviation() {
+            if deviation.abs() > MAX_DEVIATION {
+                warn!(
+                    "Found {} shares so far while the hashrate implies {:.1} ({:+.1} standard deviations), \
+                     hashes might be computed or counted wrong",
+                    share_check.found(),
+                    share_check.expected(),
+                    deviation
+                );
+            }
+        }
+    }
+
+    fn format_hashrate(rate: f64) -> String {
+        let (rate, suffix) = Self::hash_suffix(rate);
+        format!("{:.2} {}", rate, suffix)
+    }
+
     #[inline]
-    fn hash_suffix(n: f64) -> (f64, &'static str) {
+    pub fn hash_suffix(n: f64) -> (f64, &'static str) {
         match n {
             n if n < 1_000.0 => (n, "hash/s"),
             n if n < 1_000_000.0 => (n / 1_000.0, "Khash/s"),
@@ -1,44 +1,129 @@
-pub use crate::pow::hasher::HeaderHasher;
+//! kHeavyHash, the proof of work of Kaspa blocks.
+//!
+//! Without the `std` feature only [`HeaderView`], [`HeaderHasher`] and [`Matrix`] are available,
+//! which don't allocate and build for `no_std` targets.
+#[cfg(feature = "std")]
+pub use crate::pow::header::{FieldError, FromHexError, Header, HeaderError};
+pub use crate::pow::{
+    hasher::{Hasher, HeaderHasher},
+    header::HeaderView,
+    heavy_hash::Matrix,
+};
+#[cfg(feature = "std")]
 use crate::{
-    pow::{
-        hasher::{Hasher, PowHasher},
-        heavy_hash::Matrix,
-    },
+    pow::hasher::PowHasher,
     proto::{RpcBlock, RpcBlockHeader},
     target::{self, Uint256},
-    Error,
+    Hash,
 };
+#[cfg(feature = "std")]
+use std::time::Duration;
 
 mod hasher;
+mod header;
 mod heavy_hash;
 mod keccak;
+#[cfg(feature = "std")]
+mod self_test;
 mod xoshiro;
 
+#[cfg(feature = "std")]
+pub use self_test::self_test;
+
+/// The hash identifying a block, of its whole header.
+#[cfg(feature = "std")]
+pub fn block_hash(header: &RpcBlockHeader) -> Result<Hash, HeaderError> {
+    Ok(Header::try_from(header)?.block_hash())
+}
+
+/// The hash of the header without its timestamp and nonce, which the kHeavyHash [`Matrix`] is generated from.
+#[cfg(feature = "std")]
+pub fn pre_pow_hash(header: &RpcBlockHeader) -> Result<Hash, HeaderError> {
+    Ok(Header::try_from(header)?.pre_pow_hash())
+}
+
+/// The kHeavyHash result of the header with its timestamp and nonce, the block is valid if it isn't above the [`target()`].
+#[cfg(feature = "std")]
+pub fn calculate_pow(header: &RpcBlockHeader) -> Result<Uint256, HeaderError> {
+    Ok(Header::try_from(header)?.calculate_pow())
+}
+
+/// The highest PoW the header's block can have, from its compact `bits`.
+#[cfg(feature = "std")]
+pub fn target(header: &RpcBlockHeader) -> Uint256 {
+    target::u256_from_compact_target(header.bits)
+}
+
+/// Mines a block template, by trying nonces on it one by one.
+#[cfg(feature = "std")]
 #[derive(Clone)]
 pub struct State {
-    pub _id: usize,
+    pub id: usize,
     matrix: Matrix,
     pub nonce: u64,
     target: Uint256,
+    // Hashes under this are counted as shares, it's the block target unless counting shares
+    share_target: Uint256,
     block: RpcBlock,
+    // Doesn't cover the timestamp, so rolling it only needs a new hasher
+    pre_pow_hash: Hash,
+    template_timestamp: i64,
     // PRE_POW_HASH || TIME || 32 zero byte padding; without NONCE
     hasher: PowHasher,
 }
 
+#[cfg(feature = "std")]
 impl State {
     #[inline]
-    pub fn new(id: usize, block: RpcBlock) -> Result<Self, Error> {
-        let header = &block.header.as_ref().ok_or("Header is missing")?;
+    pub fn new(id: usize, block: RpcBlock) -> Result<Self, HeaderError> {
+        // Checks the whole header, so the blocks generated from it can always be hashed
+        let header = Header::try_from(block.header.as_ref().ok_or(HeaderError::Missing)?)?;
 
-        let target = target::u256_from_compact_target(header.bits);
-        let mut hasher = HeaderHasher::new();
-        serialize_header(&mut hasher, header, true);
-        let pre_pow_hash = hasher.finalize();
+        let target = header.target();
+        let pre_pow_hash = header.pre_pow_hash();
         // PRE_POW_HASH || TIME || 32 zero byte padding || NONCE
-        let hasher = PowHasher::new(pre_pow_hash, header.timestamp as u64);
+        let hasher = PowHasher::new(pre_pow_hash, header.timestamp);
         let matrix = Matrix::generate(pre_pow_hash);
 
-        Ok(Self { _id: id, matrix, nonce: 0, target, block, hasher })
+        let template_timestamp = header.timestamp as i64;
+
+        Ok(Self { id, matrix, nonce: 0, target, share_target: target, block, pre_pow_hash, template_timestamp, hasher })
+    }
+
+    /// How far a rolled timestamp can get ahead of kaspad's clock. It rejects blocks more than 132 target block times
+    /// in its future, 13.2 seconds at 10 blocks per second.
+    pub const MAX_TIMESTAMP_DRIFT: Duration = Duration::from_secs(10);
+
+    /// Moves the header's timestamp (in milliseconds) forward, but never before the template's own timestamp,
+    /// nor more than [`MAX_TIMESTAMP_DRIFT`](Self::MAX_TIMESTAMP_DRIFT) after it plus the time `since_template` was received.
+    pub fn roll_timestamp(&mut self, timestamp: i64, since_template: Duration) {
+        let ahead = (since_template + Self::MAX_TIMESTAMP_DRIFT).as_millis().try_into().unwrap_or(i64::MAX);
+        let timestamp = timestamp.clamp(self.template_timestamp, self.template_timestamp.saturating_add(ahead));
+        let header = self.block.header.as_mut().expect("We checked that a header exists on creation");
+        header.timestamp = timestamp;
+        self.hasher = PowHasher::new(self.pre_pow_hash, timestamp as u64);
+    }
+
+    /// Mines against `target` instead of the one of the header's bits, which the generated blocks keep.
+    /// Shares are counted against it too, call it before [`set_share_ratio`](Self::set_share_ratio).
+    pub fn set_target(&mut self, target: Uint256) {
+        self.target = target;
+        self.share_target = target;
+    }
+
+    /// Counts hashes under a target `ratio` times easier than the block's as shares
+    pub fn set_share_ratio(&mut self, ratio: u64) {
+        self.share_target = self.target.saturating_mul_u64(ratio);
+    }
+
+    /// The expected amount of hashes per block
+    pub fn block_work(&self) -> f64 {
+        target::expected_hashes(self.target)
+    }
+
+    /// The expected amount of hashes per share
+    pub fn share_work(&self) -> f64 {
+        target::expected_hashes(self.share_target).max(1.0)
     }
 
     #[inline(always)]
@@ -56,108 +141,63 @@ impl State {
         pow <= self.target
     }
 
+    /// Returns the PoW of the current nonce if it's a share, every block is also a share.
     #[inline(always)]
-    pub fn generate_block_if_pow(&self) -> Option<RpcBlock> {
-        self.check_pow().then(|| {
-            let mut block = self.block.clone();
-            let header = block.header.as_mut().expect("We checked that a header exists on creation");
-            header.nonce = self.nonce;
-            block
-        })
+    pub fn check_share(&self) -> Option<Uint256> {
+        let pow = self.calculate_pow();
+        (pow <= self.share_target).then_some(pow)
     }
-}
-
-#[cfg(not(any(target_pointer_width = "64", target_pointer_width = "32")))]
-compile_error!("Supporting only 32/64 bits");
 
-#[inline(always)]
-pub fn serialize_header<H: Hasher>(hasher: &mut H, header: &RpcBlockHeader, for_pre_pow: bool) {
-    let (nonce, timestamp) = if for_pre_pow { (0, 0) } else { (header.nonce, header.timestamp) };
-    let num_parents = header.parents.len();
-    let version: u16 = header.version.try_into().unwrap();
-    hasher.update(version.to_le_bytes()).update((num_parents as u64).to_le_bytes());
-
-    let mut hash = [0u8; 32];
-    for parent in &header.parents {
-        hasher.update((parent.parent_hashes.len() as u64).to_le_bytes());
-        for hash_string in &parent.parent_hashes {
-            decode_to_slice(hash_string, &mut hash).unwrap();
-            hasher.update(hash);
-        }
-    }
-    decode_to_slice(&header.hash_merkle_root, &mut hash).unwrap();
-    hasher.update(hash);
-
-    decode_to_slice(&header.accepted_id_merkle_root, &mut hash).unwrap();
-    hasher.update(hash);
-    decode_to_slice(&header.utxo_commitment, &mut hash).unwrap();
-    hasher.update(hash);
-
-    hasher
-        .update(timestamp.to_le_bytes())
-        .update(header.bits.to_le_bytes())
-        .update(nonce.to_le_bytes())
-        .update(header.daa_score.to_le_bytes())
-        .update(header.blue_score.to_le_bytes());
-
-    // I'm assuming here BlueWork will never pass 256 bits.
-    let blue_work_len = header.blue_work.len().div_ceil(2);
-    if header.blue_work.len().is_multiple_of(2) {
-        decode_to_slice(&header.blue_work, &mut hash[..blue_work_len]).unwrap();
-    } else {
-        let mut blue_work = String::with_capacity(header.blue_work.len() + 1);
-        blue_work.push('0');
-        blue_work.push_str(&header.blue_work);
-        decode_to_slice(&blue_work, &mut hash[..blue_work_len]).unwrap();
+    #[inline(always)]
+    pub fn is_block(&self, pow: Uint256) -> bool {
+        pow <= self.target
     }
 
-    hasher.update((blue_work_len as u64).to_le_bytes()).update(&hash[..blue_work_len]);
-
-    decode_to_slice(&header.pruning_point, &mut hash).unwrap();
-    hasher.update(hash);
+    /// The block with the current nonce
+    pub fn generate_block(&self) -> RpcBlock {
+        let mut block = self.block.clone();
+        let header = block.header.as_mut().expect("We checked that a header exists on creation");
+        header.nonce = self.nonce;
+        block
+    }
 }
 
-#[allow(dead_code)] // False Positive: https://github.com/rust-lang/rust/issues/88900
-#[derive(Debug)]
-enum FromHexError {
-    OddLength,
-    InvalidStringLength,
-    InvalidHexCharacter { c: char, index: usize },
+/// Recomputes the PoW of a finished header from scratch, sharing nothing with the miner threads' [`State`]
+/// and using the portable keccak instead of the one we mine with. Returns the PoW and the target decoded from `bits`.
+#[cfg(feature = "std")]
+pub fn reference_pow(header: &Header) -> (Uint256, Uint256) {
+    let pre_pow_hash = header.pre_pow_hash();
+    let hash =
+        PowHasher::new(pre_pow_hash, header.timestamp).finalize_with_nonce_using(header.nonce, keccak::f1600_portable);
+    let pow = Matrix::generate(pre_pow_hash).heavy_hash_using(hash, keccak::f1600_portable);
+    (pow, header.target())
 }
 
-#[inline(always)]
-fn decode_to_slice<T: AsRef<[u8]>>(data: T, out: &mut [u8]) -> Result<(), FromHexError> {
-    let data = data.as_ref();
-    if data.len() % 2 != 0 {
-        return Err(FromHexError::OddLength);
-    }
-    if data.len() / 2 != out.len() {
-        return Err(FromHexError::InvalidStringLength);
-    }
-
-    for (i, byte) in out.iter_mut().enumerate() {
-        *byte = val(data[2 * i], 2 * i)? << 4 | val(data[2 * i + 1], 2 * i + 1)?;
-    }
-
-    #[inline(always)]
-    fn val(c: u8, idx: usize) -> Result<u8, FromHexError> {
-        match c {
-            b'A'..=b'F' => Ok(c - b'A' + 10),
-            b'a'..=b'f' => Ok(c - b'a' + 10),
-            b'0'..=b'9' => Ok(c - b'0'),
-            _ => Err(FromHexError::InvalidHexCharacter { c: c as char, index: idx }),
-        }
-    }
+#[cfg(not(any(target_pointer_width = "64", target_pointer_width = "32")))]
+compile_error!("Supporting only 32/64 bits");
 
+/// Feeds the header to `hasher` the way kaspad serializes it for hashing, with a zeroed timestamp and nonce if `for_pre_pow`.
+#[cfg(feature = "std")]
+pub fn serialize_header<H: Hasher>(
+    hasher: &mut H,
+    header: &RpcBlockHeader,
+    for_pre_pow: bool,
+) -> Result<(), HeaderError> {
+    Header::try_from(header)?.serialize(hasher, for_pre_pow);
     Ok(())
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::pow::hasher::{Hasher, HeaderHasher};
-    use crate::pow::serialize_header;
-    use crate::proto::{RpcBlockHeader, RpcBlockLevelParents};
+    use crate::pow::self_test::header;
+    use crate::pow::{
+        block_hash, calculate_pow, reference_pow, serialize_header, FieldError, FromHexError, Header, HeaderError,
+        State,
+    };
+    use crate::proto::{self, BlockHeader, BlockLevelParents, RpcBlock, RpcBlockHeader, RpcBlockLevelParents};
     use crate::Hash;
+    use std::time::Duration;
 
     struct Buf(Vec<u8>);
     impl Hasher for Buf {
@@ -169,107 +209,7 @@ mod tests {
 
     #[test]
     fn test_serialize_header() {
-        let header = RpcBlockHeader {
-            version: 24565,
-            parents: vec![
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "62a5eee82abdf44a2d0b75fb180daf48a79ee0b10d394651850fd4a178892ee2".into(),
-                        "85ece1511455780875d64ee2d3d0d0de6bf8f9b44ce85ff044c6b1f83b8e883b".into(),
-                        "bf857aab99c5b252c7429c32f3a8aeb79ef856f659c18f0dcecc77c75e7a81bf".into(),
-                        "de275f67cfe242cf3cc354f3ede2d6becc4ea3ae5e88526a9f4a578bcb9ef2d4".into(),
-                        "a65314768d6d299761ea9e4f5aa6aec3fc78c6aae081ac8120c720efcd6cea84".into(),
-                        "b6925e607be063716f96ddcdd01d75045c3f000f8a796bce6c512c3801aacaee".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "dfad5b50ece0b8b7c1965d9181251b7c9c9ca5205afc16a236a2efcdd2d12d2a".into(),
-                        "79d074a8280ae9439eb0d6aeca0823ae02d67d866ac2c4fe4a725053da119b9d".into(),
-                        "4f515140a2d7239c40b45ac3950d941fc4fe1c0cb96ad322d62282295fbfe11e".into(),
-                        "26a433076db5c1444c3a34d32a5c4a7ffbe8d181f7ed3b8cfe904f93f8f06d29".into(),
-                        "bcd9ed847b182e046410f44bc4b0f3f03a0d06820a30f257f8114130678ac045".into(),
-                        "86c1e3c9342c8b8055c466d886441d259906d69acd894b968ae9f0eb9d965ce6".into(),
-                        "a4693c4ebe881501b7d9846b66eb02b57e5cda7b6cba6891d616bd686c37b834".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "613ac8ba52734ae4e3f1217acd5f83270814301867b5d06711b238001c7957b2".into(),
-                        "7719ce3f3188dfe57deebf6f82595a10f7bb562ca04d5c3d27942958c6db3262".into(),
-                        "670649f3bc97d9a2316735ede682a5dfe6f1a011fbc98ad0fbe790003c01e8e9".into(),
-                        "967703af665e9f72407f4b03d4fdb474aafe8a0d3e0515dd4650cf51172b8124".into(),
-                        "8bcb7f969e400b6c5b127768b1c412fae98cf57631cf37033b4b4aba7d7ed319".into(),
-                        "ba147249c908ac70d1c406dade0e828eb6ba0dcaa88285543e10213c643fc860".into(),
-                        "3b5860236670babcad0bd7f4c4190e323623a868d1eae1769f40a26631431b3b".into(),
-                        "d5215605d2086fead499ac63a4653d12283d56019c3795a98a126d09cfcbe36c".into(),
-                        "dcc93788a5409f8b6e42c2dd83aa46611852ad0b5028775c771690b6854e05b3".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "77241e302c6da8665c42341dda4adaea595ab1895f9652489dd2ceb49c247430".into(),
-                        "3cbb44c2b94303db662c9c66b8782905190f1e1635b63e34878d3f246fadfce3".into(),
-                        "44e74ef813090f8030bcd525ac10653ff182e00120f7e1f796fa0fc16ba7bb90".into(),
-                        "be2a33e87c3d60ab628471a420834383661801bb0bfd8e6c140071db1eb2f7a1".into(),
-                        "8194f1a045a94c078835c75dff2f3e836180baad9e955da840dc74c4dc2498f8".into(),
-                        "c201aec254a0e36476b2eeb124fdc6afc1b7d809c5e08b5e0e845aaf9b6c3957".into(),
-                        "e95ab4aa8e107cdb873f2dac527f16c4d5ac8760768a715e4669cb840c25317f".into(),
-                        "9a368774e506341afb46503e28e92e51bd7f7d4b53b9023d56f9b9ec991ac2a9".into(),
-                        "d9bc45ff64bb2bf14d4051a7604b28bad44d98bfe30e54ebc07fa45f62aabe39".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "5cc98b2e3f6deb2990187058e4bfd2d1640653fc38a30b0f83231a965b413b0f".into(),
-                        "26927e0d032e830b732bdeb3094cb1a5fa6dec9f06375ea25fe57c2853ea0932".into(),
-                        "0ac8803976eacaa095c02f869fd7dc31072475940c3751d56283c49e2fefd41d".into(),
-                        "f676bdcb5855a0470efd2dab7a72cc5e5f39ff7eea0f433a9fe7b6a675bc2ac5".into(),
-                        "0cd218c009e21f910f9ddb09a0d059c4cd7d2ca65a2349df7a867dbedd81e9d4".into(),
-                        "891619c83c42895ce1b671cb7a4bcaed9130ab1dd4cc2d8147a1595056b55f92".into(),
-                        "a355db765adc8d3df88eb93d527f7f7ec869a75703ba86d4b36110e9a044593c".into(),
-                        "966815d153665387dc38e507e7458df3e6b0f04035ef9419883e03c08e2d753b".into(),
-                        "08c9090aabf175fdb63e8cf9a5f0783704c741c195157626401d949eaa6dbd04".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "d7bf5e9c18cc79dda4e12efe564ecb8a4019e1c41f2d8217c0c3a43712ae226f".into(),
-                        "ce776631ae19b326a411a284741be01fb4f3aefc5def968eb6cceb8604864b4b".into(),
-                        "9ad373cbac10ea7e665b294a8a790691aa5246e6ff8fd0b7fb9b9a6a958ebf28".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "ec5e8fc0bc0c637004cee262cef12e7cf6d9cd7772513dbd466176a07ab7c4f4".into(),
-                        "65fe09747779c31495e689b65f557b0a4af6535880b82553d126ff7213542905".into(),
-                        "5a64749599333e9655b43aa36728bb63bd286427441baa9f305d5c25e05229bb".into(),
-                        "332f7e8375b7c45e1ea0461d333c3c725f7467b441b7d0f5e80242b7a4a18eda".into(),
-                    ],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec!["e80d7d9a0a4634f07bea5c5a00212fbc591bddfebb94334f4a2d928673d262ad".into()],
-                },
-                RpcBlockLevelParents {
-                    parent_hashes: vec![
-                        "abaa82988c683f4742c12099b732bd03639c1979752d837518243b74d6730124".into(),
-                        "5efe5661eaa0428917f55a58cc33db284d1f2caa05f1fd7b6602980f06d10723".into(),
-                        "0bf310b48cf62942017dd6680eb3ab13310eca1581afb3c5b619e5ce0682d0df".into(),
-                        "c1fade3928179a9dc28cd170b5b5544e7f9b63b83da374afa28e1478dc5c2997".into(),
-                    ],
-                },
-            ],
-            hash_merkle_root: "a98347ec1e71514eb26822162dc7c3992fd41f0b2ccc26e55e7bd8f3fa37215f".into(),
-            accepted_id_merkle_root: "774b5216b5b872b6c2388dd950160e3ffa3bf0623c438655bb5c8c768ab33ae2".into(),
-            utxo_commitment: "ee39218674008665e20a3acdf84abef35cabcc489158c0853fd5bfa954226139".into(),
-            timestamp: -1426594953012613626,
-            bits: 684408190,
-            nonce: 8230160685758639177,
-            daa_score: 15448880227546599629,
-            blue_work: "ce5639b8ed46571e20eeaa7a62a078f8c55aef6edd6a35ed37a3d6cf98736abd".into(),
-            pruning_point: "fc44c4f57cf8f7a2ba410a70d0ad49060355b9deb97012345603d9d0d1dcb0de".into(),
-            blue_score: 29372123613087746,
-        };
+        let header = header();
         let expected_res = [
             245, 95, 9, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 98, 165, 238, 232, 42, 189, 244, 74, 45, 11, 117,
             251, 24, 13, 175, 72, 167, 158, 224, 177, 13, 57, 70, 81, 133, 15, 212, 161, 120, 137, 46, 226, 133, 236,
@@ -356,7 +296,7 @@ mod tests {
             52, 86, 3, 217, 208, 209, 220, 176, 222,
         ];
         let mut buf = Buf(Vec::with_capacity(1951));
-        serialize_header(&mut buf, &header, true);
+        serialize_header(&mut buf, &header, true).unwrap();
         assert_eq!(&expected_res[..], &buf.0);
 
         let expected_hash = Hash::from_le_bytes([
@@ -367,4 +307,134 @@ mod tests {
         hasher.write(buf.0);
         assert_eq!(hasher.finalize(), expected_hash);
     }
+
+    #[test]
+    fn test_reference_pow() {
+        let block = RpcBlock { header: Some(header()), transactions: vec![], verbose_data: None };
+        let mut state = State::new(0, block).unwrap();
+        for nonce in [0, 1, 432432432, u64::MAX] {
+            state.nonce = nonce;
+            let mut header = header();
+            header.nonce = nonce;
+            let (pow, target) = reference_pow(&Header::try_from(&header).unwrap());
+            assert_eq!(pow, state.calculate_pow());
+            assert_eq!(pow, calculate_pow(&header).unwrap());
+            assert_eq!(target, crate::pow::target(&header));
+            assert_eq!(state.check_share().is_some_and(|pow| state.is_block(pow)), pow <= target);
+            assert_eq!(state.generate_block().block_hash(), block_hash(&header));
+            assert_eq!(state.generate_block().header, Some(header));
+        }
+    }
+
+    #[test]
+    fn test_roll_timestamp() {
+        let block = RpcBlock { header: Some(header()), transactions: vec![], verbose_data: None };
+        let mut state = State::new(0, block).unwrap();
+        let template_timestamp = header().timestamp;
+        let since_template = Duration::from_secs(60);
+        // The template's timestamp, plus the minute it was mined, plus the drift kaspad tolerates
+        let latest = template_timestamp + 70_000;
+        for (timestamp, expected) in [
+            (template_timestamp + 1000, template_timestamp + 1000),
+            (template_timestamp - 1000, template_timestamp),
+            (latest, latest),
+            // A local clock far ahead of kaspad's
+            (latest + 1, latest),
+            (i64::MAX, latest),
+        ] {
+            state.roll_timestamp(timestamp, since_template);
+            let block = state.generate_block();
+            let header = block.header.unwrap();
+            assert_eq!(header.timestamp, expected);
+            assert_eq!(reference_pow(&Header::try_from(&header).unwrap()).0, state.calculate_pow());
+        }
+    }
+
+    #[test]
+    fn test_header_errors() {
+        let invalid = |edit: fn(&mut RpcBlockHeader)| {
+            let mut header = header();
+            edit(&mut header);
+            State::new(0, RpcBlock { header: Some(header), transactions: vec![], verbose_data: None }).err()
+        };
+        assert_eq!(
+            invalid(|header| header.hash_merkle_root.truncate(63)),
+            Some(HeaderError::InvalidHash { field: "hashMerkleRoot", error: FieldError::Hex(FromHexError::OddLength) })
+        );
+        assert_eq!(
+            invalid(|header| header.pruning_point.replace_range(4..5, "g")),
+            Some(HeaderError::InvalidHash {
+                field: "pruningPoint",
+                error: FieldError::Hex(FromHexError::InvalidHexCharacter { c: 'g', index: 4 })
+            })
+        );
+        assert_eq!(
+            invalid(|header| header
+                .parents
+                .push(RpcBlockLevelParents { parent_hashes: vec!["00".repeat(32), "00".into()] })),
+            Some(HeaderError::InvalidParent {
+                level: header().parents.len(),
+                index: 1,
+                error: FieldError::Hex(FromHexError::InvalidStringLength)
+            })
+        );
+        assert_eq!(
+            invalid(|header| header.blue_work = "1".repeat(65)),
+            Some(HeaderError::InvalidBlueWork(FieldError::Length(33)))
+        );
+        assert_eq!(invalid(|header| header.version = 1 << 16), Some(HeaderError::InvalidVersion(1 << 16)));
+        assert_eq!(State::new(0, RpcBlock::default()).err(), Some(HeaderError::Missing));
+        assert_eq!(RpcBlock::default().block_hash(), Err(HeaderError::Missing));
+        assert!(invalid(|header| header.blue_work = "f".repeat(63)).is_none());
+    }
+
+    #[test]
+    fn test_p2p_header() {
+        let rpc_header = header();
+        let decoded = Header::try_from(&rpc_header).unwrap();
+        let hash = |bytes: &[u8; 32]| proto::Hash { bytes: bytes.to_vec() };
+        let blue_work = decoded.blue_work.to_le_bytes().into_iter().rev().skip_while(|&byte| byte == 0).collect();
+        let mut p2p_header = BlockHeader {
+            version: rpc_header.version,
+            parents: decoded
+                .parents
+                .iter()
+                .map(|level| BlockLevelParents { parent_hashes: level.iter().map(hash).collect() })
+                .collect(),
+            hash_merkle_root: Some(hash(&decoded.hash_merkle_root)),
+            accepted_id_merkle_root: Some(hash(&decoded.accepted_id_merkle_root)),
+            utxo_commitment: Some(hash(&decoded.utxo_commitment)),
+            timestamp: rpc_header.timestamp,
+            bits: rpc_header.bits,
+            nonce: rpc_header.nonce,
+            daa_score: rpc_header.daa_score,
+            blue_work,
+            pruning_point: Some(hash(&decoded.pruning_point)),
+            blue_score: rpc_header.blue_score,
+        };
+        assert_eq!(Header::try_from(&p2p_header).unwrap(), decoded);
+        assert_eq!(decoded.block_hash(), block_hash(&rpc_header).unwrap());
+        assert_eq!(p2p_header.block_hash(), Ok(decoded.block_hash()));
+        assert_eq!(BlockHeader::from(&decoded), p2p_header);
+        assert_eq!(RpcBlockHeader::from(&decoded), rpc_header);
+        assert_eq!(BlockHeader::try_from(&rpc_header), Ok(p2p_header.clone()));
+        assert_eq!(RpcBlockHeader::try_from(&p2p_header), Ok(rpc_header.clone()));
+
+        // kaspad hashes the blue work without its leading zeros
+        let short = RpcBlockHeader { blue_work: "1abc".to_string(), ..rpc_header.clone() };
+        let padded = RpcBlockHeader { blue_work: "0001abc".to_string(), ..rpc_header.clone() };
+        assert_eq!(Header::try_from(&padded).unwrap().block_hash(), block_hash(&short).unwrap());
+
+        p2p_header.parents[1].parent_hashes[2].bytes.pop();
+        assert_eq!(
+            Header::try_from(&p2p_header),
+            Err(HeaderError::InvalidParent { level: 1, index: 2, error: FieldError::Length(31) })
+        );
+        p2p_header.parents[1].parent_hashes[2].bytes.push(0);
+        p2p_header.utxo_commitment = None;
+        assert_eq!(
+            Header::try_from(&p2p_header),
+            Err(HeaderError::InvalidHash { field: "utxoCommitment", error: FieldError::Missing })
+        );
+    }
 }
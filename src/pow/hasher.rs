@@ -10,6 +10,7 @@ pub(super) struct PowHasher([u64; 25]);
 #[derive(Clone, Copy)]
 pub(super) struct HeavyHasher;
 
+/// The keyed blake2b block headers are hashed with, see [`serialize_header`](super::serialize_header).
 #[derive(Clone)]
 pub struct HeaderHasher(Blake2bState);
 
@@ -36,9 +37,14 @@ impl PowHasher {
     }
 
     #[inline(always)]
-    pub(super) fn finalize_with_nonce(mut self, nonce: u64) -> Hash {
+    pub(super) fn finalize_with_nonce(self, nonce: u64) -> Hash {
+        self.finalize_with_nonce_using(nonce, super::keccak::f1600)
+    }
+
+    #[inline(always)]
+    pub(super) fn finalize_with_nonce_using(mut self, nonce: u64, f1600: fn(&mut [u64; 25])) -> Hash {
         self.0[9] ^= nonce;
-        super::keccak::f1600(&mut self.0);
+        f1600(&mut self.0);
         Hash::new(self.0[..4].try_into().unwrap())
     }
 }
@@ -55,17 +61,29 @@ impl HeavyHasher {
         8596393687355028144, 570094237299545110, 9119540418498120711, 16901969272480492857, 13372017233735502424,
         14372891883993151831, 5171152063242093102, 10573107899694386186, 6096431547456407061, 1592359455985097269,
     ];
+    #[cfg(feature = "std")]
     #[inline(always)]
     pub(super) fn hash(in_hash: Hash) -> Hash {
+        Self::hash_using(in_hash, super::keccak::f1600)
+    }
+
+    #[inline(always)]
+    pub(super) fn hash_using(in_hash: Hash, f1600: fn(&mut [u64; 25])) -> Hash {
         let mut state = Self::INITIAL_STATE;
         for (&pre_pow_word, state_word) in in_hash.0.iter().zip(state.iter_mut()) {
             *state_word ^= pre_pow_word;
         }
-        super::keccak::f1600(&mut state);
+        f1600(&mut state);
         Hash::new(state[..4].try_into().unwrap())
     }
 }
 
+impl Default for HeaderHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HeaderHasher {
     #[inline(always)]
     pub fn new() -> Self {
@@ -82,6 +100,7 @@ impl HeaderHasher {
     }
 }
 
+/// Something the serialized header can be fed to
 pub trait Hasher {
     fn update<A: AsRef<[u8]>>(&mut self, data: A) -> &mut Self;
 }
@@ -93,7 +112,7 @@ impl Hasher for HeaderHasher {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::pow::hasher::{HeavyHasher, PowHasher};
     use crate::Hash;
@@ -0,0 +1,495 @@
+#[cfg(feature = "std")]
+use crate::proto::{self, BlockHeader, RpcBlockHeader};
+use crate::{
+    pow::{
+        hasher::{Hasher, HeaderHasher},
+        heavy_hash::Matrix,
+    },
+    target::{self, Uint256},
+    Hash,
+};
+#[cfg(feature = "std")]
+use std::fmt;
+
+/// A block header with its hashes already decoded, which can be hashed without `std` or allocating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderView<'a> {
+    pub version: u16,
+    /// The parent hashes of every level
+    pub parents: &'a [&'a [[u8; 32]]],
+    pub hash_merkle_root: [u8; 32],
+    pub accepted_id_merkle_root: [u8; 32],
+    pub utxo_commitment: [u8; 32],
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub daa_score: u64,
+    /// Big-endian, as long as it's sent in kaspad's messages
+    pub blue_work: &'a [u8],
+    pub blue_score: u64,
+    pub pruning_point: [u8; 32],
+}
+
+impl HeaderView<'_> {
+    /// Feeds the header to `hasher` the way kaspad serializes it for hashing, with a zeroed timestamp and nonce if `for_pre_pow`.
+    #[inline(always)]
+    pub fn serialize<H: Hasher>(&self, hasher: &mut H, for_pre_pow: bool) {
+        let (nonce, timestamp) = if for_pre_pow { (0, 0) } else { (self.nonce, self.timestamp) };
+        hasher.update(self.version.to_le_bytes()).update((self.parents.len() as u64).to_le_bytes());
+        for level in self.parents {
+            hasher.update((level.len() as u64).to_le_bytes());
+            for parent in *level {
+                hasher.update(parent);
+            }
+        }
+        hasher.update(self.hash_merkle_root).update(self.accepted_id_merkle_root).update(self.utxo_commitment);
+        hasher
+            .update(timestamp.to_le_bytes())
+            .update(self.bits.to_le_bytes())
+            .update(nonce.to_le_bytes())
+            .update(self.daa_score.to_le_bytes())
+            .update(self.blue_score.to_le_bytes());
+        hasher.update((self.blue_work.len() as u64).to_le_bytes()).update(self.blue_work);
+        hasher.update(self.pruning_point);
+    }
+
+    /// The hash identifying the block, of the whole header.
+    pub fn block_hash(&self) -> Hash {
+        let mut hasher = HeaderHasher::new();
+        self.serialize(&mut hasher, false);
+        hasher.finalize()
+    }
+
+    /// The hash of the header without its timestamp and nonce, which the kHeavyHash [`Matrix`] is generated from.
+    pub fn pre_pow_hash(&self) -> Hash {
+        let mut hasher = HeaderHasher::new();
+        self.serialize(&mut hasher, true);
+        hasher.finalize()
+    }
+
+    /// The kHeavyHash result of the header, the block is valid if it isn't above the [`target`](Self::target).
+    pub fn calculate_pow(&self) -> Uint256 {
+        let pre_pow_hash = self.pre_pow_hash();
+        Matrix::generate(pre_pow_hash).pow(pre_pow_hash, self.timestamp, self.nonce)
+    }
+
+    /// The highest PoW the block can have, from its compact `bits`.
+    pub fn target(&self) -> Uint256 {
+        target::u256_from_compact_target(self.bits)
+    }
+}
+
+/// A block header with its hashes decoded and checked, converted once from kaspad's RPC or p2p messages.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    /// The parent hashes of every level
+    pub parents: Vec<Vec<[u8; 32]>>,
+    pub hash_merkle_root: [u8; 32],
+    pub accepted_id_merkle_root: [u8; 32],
+    pub utxo_commitment: [u8; 32],
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub daa_score: u64,
+    pub blue_work: Uint256,
+    pub blue_score: u64,
+    pub pruning_point: [u8; 32],
+}
+
+#[cfg(feature = "std")]
+impl Header {
+    /// Calls `f` with the header's [`HeaderView`], which is what's hashed.
+    pub fn view<R>(&self, f: impl FnOnce(&HeaderView) -> R) -> R {
+        let parents: Vec<&[[u8; 32]]> = self.parents.iter().map(Vec::as_slice).collect();
+        let blue_work = blue_work_bytes(self.blue_work);
+        f(&HeaderView {
+            version: self.version,
+            parents: &parents,
+            hash_merkle_root: self.hash_merkle_root,
+            accepted_id_merkle_root: self.accepted_id_merkle_root,
+            utxo_commitment: self.utxo_commitment,
+            timestamp: self.timestamp,
+            bits: self.bits,
+            nonce: self.nonce,
+            daa_score: self.daa_score,
+            blue_work: &blue_work[blue_work.iter().take_while(|&&byte| byte == 0).count()..],
+            blue_score: self.blue_score,
+            pruning_point: self.pruning_point,
+        })
+    }
+
+    /// Feeds the header to `hasher` the way kaspad serializes it for hashing, with a zeroed timestamp and nonce if `for_pre_pow`.
+    pub fn serialize<H: Hasher>(&self, hasher: &mut H, for_pre_pow: bool) {
+        self.view(|view| view.serialize(hasher, for_pre_pow))
+    }
+
+    /// The hash identifying the block, of the whole header.
+    pub fn block_hash(&self) -> Hash {
+        self.view(|view| view.block_hash())
+    }
+
+    /// The hash of the header without its timestamp and nonce, which the kHeavyHash [`Matrix`] is generated from.
+    pub fn pre_pow_hash(&self) -> Hash {
+        self.view(|view| view.pre_pow_hash())
+    }
+
+    /// The kHeavyHash result of the header, the block is valid if it isn't above the [`target`](Self::target).
+    pub fn calculate_pow(&self) -> Uint256 {
+        self.view(|view| view.calculate_pow())
+    }
+
+    /// The highest PoW the block can have, from its compact `bits`.
+    pub fn target(&self) -> Uint256 {
+        target::u256_from_compact_target(self.bits)
+    }
+}
+
+/// The blue work as kaspad serializes it, big-endian without leading zeros once they're skipped
+#[cfg(feature = "std")]
+fn blue_work_bytes(blue_work: Uint256) -> [u8; 32] {
+    let mut bytes = blue_work.to_le_bytes();
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(feature = "std")]
+fn blue_work_from_bytes(bytes: &[u8]) -> Result<Uint256, FieldError> {
+    if bytes.len() > 32 {
+        return Err(FieldError::Length(bytes.len()));
+    }
+    let mut le_bytes = [0u8; 32];
+    le_bytes[..bytes.len()].copy_from_slice(bytes);
+    le_bytes[..bytes.len()].reverse();
+    Ok(Uint256::from_le_bytes(le_bytes))
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<&RpcBlockHeader> for Header {
+    type Error = HeaderError;
+
+    fn try_from(header: &RpcBlockHeader) -> Result<Self, HeaderError> {
+        let hash = |field, hash_string: &str| {
+            decode_hash(hash_string).map_err(|error| HeaderError::InvalidHash { field, error: FieldError::Hex(error) })
+        };
+        let parents = header
+            .parents
+            .iter()
+            .enumerate()
+            .map(|(level, parents)| {
+                parents
+                    .parent_hashes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, hash)| {
+                        decode_hash(hash).map_err(|error| HeaderError::InvalidParent {
+                            level,
+                            index,
+                            error: FieldError::Hex(error),
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+
+        // kaspad's RPC writes the blue work in hex without leading zeros, so it can have an odd length
+        let mut blue_work = [0u8; 32];
+        let blue_work_len = header.blue_work.len().div_ceil(2);
+        if blue_work_len > 32 {
+            return Err(HeaderError::InvalidBlueWork(FieldError::Length(blue_work_len)));
+        }
+        let blue_work_hex = if header.blue_work.len().is_multiple_of(2) {
+            decode_to_slice(&header.blue_work, &mut blue_work[..blue_work_len])
+        } else {
+            let mut padded = String::with_capacity(header.blue_work.len() + 1);
+            padded.push('0');
+            padded.push_str(&header.blue_work);
+            decode_to_slice(&padded, &mut blue_work[..blue_work_len])
+        };
+        blue_work_hex.map_err(|error| HeaderError::InvalidBlueWork(FieldError::Hex(error)))?;
+
+        Ok(Self {
+            version: header.version.try_into().map_err(|_| HeaderError::InvalidVersion(header.version))?,
+            parents,
+            hash_merkle_root: hash("hashMerkleRoot", &header.hash_merkle_root)?,
+            accepted_id_merkle_root: hash("acceptedIdMerkleRoot", &header.accepted_id_merkle_root)?,
+            utxo_commitment: hash("utxoCommitment", &header.utxo_commitment)?,
+            timestamp: header.timestamp as u64,
+            bits: header.bits,
+            nonce: header.nonce,
+            daa_score: header.daa_score,
+            blue_work: blue_work_from_bytes(&blue_work[..blue_work_len]).map_err(HeaderError::InvalidBlueWork)?,
+            blue_score: header.blue_score,
+            pruning_point: hash("pruningPoint", &header.pruning_point)?,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<&BlockHeader> for Header {
+    type Error = HeaderError;
+
+    fn try_from(header: &BlockHeader) -> Result<Self, HeaderError> {
+        let hash = |field, hash: &Option<proto::Hash>| {
+            p2p_hash(hash.as_ref()).map_err(|error| HeaderError::InvalidHash { field, error })
+        };
+        let parents = header
+            .parents
+            .iter()
+            .enumerate()
+            .map(|(level, parents)| {
+                parents
+                    .parent_hashes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, hash)| {
+                        p2p_hash(Some(hash)).map_err(|error| HeaderError::InvalidParent { level, index, error })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            version: header.version.try_into().map_err(|_| HeaderError::InvalidVersion(header.version))?,
+            parents,
+            hash_merkle_root: hash("hashMerkleRoot", &header.hash_merkle_root)?,
+            accepted_id_merkle_root: hash("acceptedIdMerkleRoot", &header.accepted_id_merkle_root)?,
+            utxo_commitment: hash("utxoCommitment", &header.utxo_commitment)?,
+            timestamp: header.timestamp as u64,
+            bits: header.bits,
+            nonce: header.nonce,
+            daa_score: header.daa_score,
+            blue_work: blue_work_from_bytes(&header.blue_work).map_err(HeaderError::InvalidBlueWork)?,
+            blue_score: header.blue_score,
+            pruning_point: hash("pruningPoint", &header.pruning_point)?,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Header> for BlockHeader {
+    fn from(header: &Header) -> Self {
+        let hash = |hash: &[u8; 32]| proto::Hash { bytes: hash.to_vec() };
+        Self {
+            version: header.version.into(),
+            parents: header
+                .parents
+                .iter()
+                .map(|level| proto::BlockLevelParents { parent_hashes: level.iter().map(hash).collect() })
+                .collect(),
+            hash_merkle_root: Some(hash(&header.hash_merkle_root)),
+            accepted_id_merkle_root: Some(hash(&header.accepted_id_merkle_root)),
+            utxo_commitment: Some(hash(&header.utxo_commitment)),
+            timestamp: header.timestamp as i64,
+            bits: header.bits,
+            nonce: header.nonce,
+            daa_score: header.daa_score,
+            blue_work: header.view(|view| view.blue_work.to_vec()),
+            pruning_point: Some(hash(&header.pruning_point)),
+            blue_score: header.blue_score,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Header> for RpcBlockHeader {
+    fn from(header: &Header) -> Self {
+        Self {
+            version: header.version.into(),
+            parents: header
+                .parents
+                .iter()
+                .map(|level| proto::RpcBlockLevelParents {
+                    parent_hashes: level.iter().map(|hash| encode_hex(hash)).collect(),
+                })
+                .collect(),
+            hash_merkle_root: encode_hex(&header.hash_merkle_root),
+            accepted_id_merkle_root: encode_hex(&header.accepted_id_merkle_root),
+            utxo_commitment: encode_hex(&header.utxo_commitment),
+            timestamp: header.timestamp as i64,
+            bits: header.bits,
+            nonce: header.nonce,
+            daa_score: header.daa_score,
+            // Like kaspad, without leading zeros
+            blue_work: header.view(|view| encode_hex(view.blue_work)).trim_start_matches('0').to_string(),
+            blue_score: header.blue_score,
+            pruning_point: encode_hex(&header.pruning_point),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(feature = "std")]
+fn p2p_hash(hash: Option<&proto::Hash>) -> Result<[u8; 32], FieldError> {
+    let bytes = &hash.ok_or(FieldError::Missing)?.bytes;
+    bytes.as_slice().try_into().map_err(|_| FieldError::Length(bytes.len()))
+}
+
+#[cfg(feature = "std")]
+fn decode_hash(hash_string: &str) -> Result<[u8; 32], FromHexError> {
+    let mut hash = [0u8; 32];
+    decode_to_slice(hash_string, &mut hash).map(|()| hash)
+}
+
+/// Why a header kaspad sent can't be hashed
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The block has no header
+    Missing,
+    /// The version doesn't fit in 16 bits
+    InvalidVersion(u32),
+    /// A hash field isn't 32 bytes
+    InvalidHash { field: &'static str, error: FieldError },
+    /// A parent hash isn't 32 bytes
+    InvalidParent { level: usize, index: usize, error: FieldError },
+    /// The blue work is above 256 bits or isn't hex
+    InvalidBlueWork(FieldError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::Missing => write!(f, "The block has no header"),
+            HeaderError::InvalidVersion(version) => {
+                write!(f, "Invalid header version {}, it must fit in 16 bits", version)
+            }
+            HeaderError::InvalidHash { field, error } => write!(f, "Invalid {} in the header: {}", field, error),
+            HeaderError::InvalidParent { level, index, error } => {
+                write!(f, "Invalid parent {} of level {} in the header: {}", index, level, error)
+            }
+            HeaderError::InvalidBlueWork(error) => write!(f, "Invalid blueWork in the header: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeaderError {}
+
+/// Why a header field can't be decoded
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldError {
+    /// The p2p message doesn't have it
+    Missing,
+    /// It has this many bytes, more than fit or less than a hash
+    Length(usize),
+    /// The RPC message's hex is malformed
+    Hex(FromHexError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing => write!(f, "missing"),
+            FieldError::Length(len) => write!(f, "{} bytes long", len),
+            FieldError::Hex(error) => error.fmt(f),
+        }
+    }
+}
+
+/// Why a hex string can't be decoded
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FromHexError {
+    OddLength,
+    InvalidStringLength,
+    InvalidHexCharacter { c: char, index: usize },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for FromHexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromHexError::OddLength => write!(f, "odd number of hex digits"),
+            FromHexError::InvalidStringLength => write!(f, "wrong number of hex digits"),
+            FromHexError::InvalidHexCharacter { c, index } => write!(f, "invalid character {:?} at {}", c, index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[inline(always)]
+fn decode_to_slice<T: AsRef<[u8]>>(data: T, out: &mut [u8]) -> Result<(), FromHexError> {
+    let data = data.as_ref();
+    if data.len() % 2 != 0 {
+        return Err(FromHexError::OddLength);
+    }
+    if data.len() / 2 != out.len() {
+        return Err(FromHexError::InvalidStringLength);
+    }
+
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = val(data[2 * i], 2 * i)? << 4 | val(data[2 * i + 1], 2 * i + 1)?;
+    }
+
+    #[inline(always)]
+    fn val(c: u8, idx: usize) -> Result<u8, FromHexError> {
+        match c {
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'0'..=b'9' => Ok(c - b'0'),
+            _ => Err(FromHexError::InvalidHexCharacter { c: c as char, index: idx }),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pow::HeaderView;
+    use crate::Hash;
+
+    #[test]
+    fn test_header_view() {
+        let parents: [&[[u8; 32]]; 2] = [&[[1; 32], [2; 32]], &[[3; 32]]];
+        let header = HeaderView {
+            version: 1,
+            parents: &parents,
+            hash_merkle_root: [4; 32],
+            accepted_id_merkle_root: [5; 32],
+            utxo_commitment: [6; 32],
+            timestamp: 1_700_000_000_000,
+            bits: 0x207fffff,
+            nonce: 42,
+            daa_score: 7,
+            blue_work: &[0x01, 0xab, 0xcd],
+            blue_score: 8,
+            pruning_point: [9; 32],
+        };
+        assert_eq!(
+            header.block_hash(),
+            Hash::from_le_bytes([
+                102, 241, 227, 200, 206, 148, 149, 40, 242, 141, 65, 252, 74, 181, 228, 246, 116, 149, 110, 98, 244,
+                168, 153, 55, 200, 21, 75, 0, 63, 67, 61, 195
+            ])
+        );
+        assert_eq!(
+            header.pre_pow_hash(),
+            Hash::from_le_bytes([
+                143, 173, 5, 173, 126, 195, 42, 192, 60, 231, 143, 50, 50, 200, 228, 221, 203, 75, 2, 176, 91, 202,
+                228, 248, 54, 122, 72, 176, 127, 103, 53, 221
+            ])
+        );
+        let pow = Hash::from_le_bytes([
+            223, 205, 96, 219, 20, 73, 67, 148, 52, 64, 30, 178, 105, 114, 135, 29, 187, 116, 83, 108, 34, 174, 254,
+            189, 19, 172, 141, 97, 3, 33, 141, 117,
+        ]);
+        assert_eq!(header.calculate_pow(), pow);
+        assert!(pow <= header.target());
+
+        // Mining changes the block hash and PoW, but not what the matrix is generated from
+        let mined = HeaderView { timestamp: header.timestamp + 1000, nonce: 43, ..header };
+        assert_ne!(mined.block_hash(), header.block_hash());
+        assert_eq!(mined.pre_pow_hash(), header.pre_pow_hash());
+        assert_ne!(mined.calculate_pow(), pow);
+    }
+}
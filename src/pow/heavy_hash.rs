@@ -1,9 +1,13 @@
 use crate::{
-    pow::{hasher::HeavyHasher, xoshiro::XoShiRo256PlusPlus},
+    pow::{
+        hasher::{HeavyHasher, PowHasher},
+        xoshiro::XoShiRo256PlusPlus,
+    },
     Hash,
 };
-use std::mem::MaybeUninit;
+use core::mem::MaybeUninit;
 
+/// The 64x64 matrix of 4 bit values kHeavyHash multiplies the PoW hash by, generated from the pre-PoW hash.
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Matrix([[u16; 64]; 64]);
 
@@ -26,6 +30,7 @@ impl Matrix {
     //     }
     // }
 
+    /// Generates the matrix of the pre-PoW hash, retrying until it has full rank.
     #[inline(always)]
     pub fn generate(hash: Hash) -> Self {
         let mut generator = XoShiRo256PlusPlus::new(hash);
@@ -62,7 +67,7 @@ impl Matrix {
             });
         });
         // SAFETY: The loop above wrote into all indexes.
-        unsafe { std::mem::transmute(out) }
+        unsafe { core::mem::transmute(out) }
     }
 
     pub fn compute_rank(&self) -> usize {
@@ -100,7 +105,18 @@ impl Matrix {
         rank
     }
 
+    /// The kHeavyHash of the PoW hash, i.e. of the header with its timestamp and nonce.
     pub fn heavy_hash(&self, hash: Hash) -> Hash {
+        self.heavy_hash_using(hash, super::keccak::f1600)
+    }
+
+    /// The kHeavyHash result of a nonce, with the matrix generated from `pre_pow_hash`.
+    pub fn pow(&self, pre_pow_hash: Hash, timestamp: u64, nonce: u64) -> Hash {
+        self.heavy_hash(PowHasher::new(pre_pow_hash, timestamp).finalize_with_nonce(nonce))
+    }
+
+    #[inline(always)]
+    pub(super) fn heavy_hash_using(&self, hash: Hash, f1600: fn(&mut [u64; 25])) -> Hash {
         let hash = hash.to_le_bytes();
         // SAFETY: An uninitialized MaybrUninit is always safe.
         let mut vec: [MaybeUninit<u8>; 64] = unsafe { MaybeUninit::uninit().assume_init() };
@@ -109,7 +125,7 @@ impl Matrix {
             vec[2 * i + 1].write(hash[i] & 0x0F);
         }
         // SAFETY: The loop above wrote into all indexes.
-        let vec: [u8; 64] = unsafe { std::mem::transmute(vec) };
+        let vec: [u8; 64] = unsafe { core::mem::transmute(vec) };
 
         // Matrix-vector multiplication, convert to 4 bits, and then combine back to 8 bits.
         let mut product: [u8; 32] = array_from_fn(|i| {
@@ -124,7 +140,7 @@ impl Matrix {
 
         // Concatenate 4 LSBs back to 8 bit xor with sum1
         product.iter_mut().zip(hash).for_each(|(p, h)| *p ^= h);
-        HeavyHasher::hash(Hash::from_le_bytes(product))
+        HeavyHasher::hash_using(Hash::from_le_bytes(product), f1600)
     }
 }
 
@@ -140,7 +156,7 @@ where
     })
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::pow::heavy_hash::Matrix;
     use crate::pow::xoshiro::XoShiRo256PlusPlus;
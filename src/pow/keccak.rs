@@ -1,12 +1,18 @@
-#[cfg(any(not(target_arch = "x86_64"), feature = "no-asm"))]
+#[cfg(not(keccak_asm))]
 pub(super) fn f1600(state: &mut [u64; 25]) {
     keccak::f1600(state);
 }
 
-#[cfg(all(target_arch = "x86_64", not(feature = "no-asm")))]
+#[cfg(keccak_asm)]
 pub(super) fn f1600(state: &mut [u64; 25]) {
     extern "C" {
         fn KeccakF1600(state: &mut [u64; 25]);
     }
     unsafe { KeccakF1600(state) }
 }
+
+/// The generic implementation, whatever the target and features are, to double check the one above
+#[cfg(feature = "std")]
+pub(super) fn f1600_portable(state: &mut [u64; 25]) {
+    keccak::keccak_p(state, 24);
+}
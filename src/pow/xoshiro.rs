@@ -1,5 +1,5 @@
 use crate::Hash;
-use std::num::Wrapping;
+use core::num::Wrapping;
 
 pub(super) struct XoShiRo256PlusPlus {
     s0: Wrapping<u64>,
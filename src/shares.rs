@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How many shares have to be expected before their amount says anything about the hashrate
+const MIN_EXPECTED_SHARES: f64 = 10.0;
+
+/// Shares found by all the miner threads since the last time they were taken
+#[derive(Debug, Default)]
+pub struct ShareCounter {
+    shares: AtomicU64,
+    /// The bits of the expected amount of hashes per share on the current template, an f64 as it can be above `u64::MAX`
+    share_work: AtomicU64,
+}
+
+impl ShareCounter {
+    #[inline]
+    pub fn add(&self) {
+        self.shares.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_share_work(&self, share_work: f64) {
+        self.share_work.store(share_work.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Takes the shares found so far, together with the current expected hashes per share
+    pub fn take(&self) -> (u64, f64) {
+        (self.shares.swap(0, Ordering::Relaxed), f64::from_bits(self.share_work.load(Ordering::Relaxed)))
+    }
+}
+
+/// Compares the shares found over the whole session to the ones the counted hashes should have found.
+#[derive(Debug, Default)]
+pub struct ShareCheck {
+    expected: f64,
+    found: u64,
+}
+
+impl ShareCheck {
+    pub fn add(&mut self, hashes: u64, shares: u64, share_work: f64) {
+        self.expected += hashes as f64 / share_work.max(1.0);
+        self.found += shares;
+    }
+
+    pub fn expected(&self) -> f64 {
+        self.expected
+    }
+
+    pub fn found(&self) -> u64 {
+        self.found
+    }
+
+    /// How many standard deviations the shares found are away from the expected amount,
+    /// `None` until enough are expected to tell.
+    pub fn deviation(&self) -> Option<f64> {
+        // Shares are a Poisson process, so the variance is the expected amount
+        (self.expected >= MIN_EXPECTED_SHARES).then(|| (self.found as f64 - self.expected) / self.expected.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shares::{ShareCheck, ShareCounter};
+
+    #[test]
+    fn test_share_check() {
+        let counter = ShareCounter::default();
+        counter.set_share_work(1000.0);
+        (0..3).for_each(|_| counter.add());
+        assert_eq!(counter.take(), (3, 1000.0));
+        assert_eq!(counter.take(), (0, 1000.0));
+        // At the network's difficulty with a ratio of 1, a share is worth more hashes than a u64 holds
+        counter.set_share_work(1e25);
+        assert_eq!(counter.take(), (0, 1e25));
+
+        let mut check = ShareCheck::default();
+        check.add(5_000, 3, 1000.0);
+        assert_eq!(check.deviation(), None);
+        check.add(95_000, 97, 1000.0);
+        assert_eq!((check.expected(), check.found()), (100.0, 100));
+        assert_eq!(check.deviation(), Some(0.0));
+        // Half the shares are missing, like when half the hashes are garbage
+        check.add(100_000, 0, 1000.0);
+        assert_eq!(check.deviation(), Some(-100.0 / 200f64.sqrt()));
+    }
+}
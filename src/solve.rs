@@ -0,0 +1,158 @@
+use crate::{
+    cli::SolveOpt,
+    message_file::{self, MessageFormat},
+    miner::{get_num_cpus, MinerConfig, MinerManager},
+    proto::{
+        kaspad_message::Payload, GetBlockTemplateResponseMessage, KaspadMessage, RpcBlock, SubmitBlockRequestMessage,
+    },
+    stats::SessionStats,
+    submissions::Submissions,
+    target, Error, ShutdownHandler,
+};
+use log::{info, warn};
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+
+/// Stands for the payout address in the session stats, the template already has one
+const PAY_ADDRESS: &str = "template";
+
+/// The block of an `RpcBlock` or of a `GetBlockTemplateResponse`, whichever the bytes are.
+/// The header is checked, so mining it can't fail.
+fn template_block(bytes: &[u8], format: MessageFormat) -> Result<RpcBlock, Error> {
+    // A template response never has the block's header field, so it can't be mistaken for a block
+    if let Ok(block @ RpcBlock { header: Some(_), .. }) = message_file::decode(bytes, format) {
+        block.block_hash()?;
+        return Ok(block);
+    }
+    let template: GetBlockTemplateResponseMessage = message_file::decode(bytes, format)?;
+    if let Some(error) = template.error {
+        return Err(format!("The template has an error: {}", error.message).into());
+    }
+    if !template.is_synced {
+        warn!("The template is from a kaspad that wasn't synced, kaspad might not accept the block");
+    }
+    let block =
+        template.block.filter(|block| block.header.is_some()).ok_or("Neither a block nor a template with a header")?;
+    block.block_hash()?;
+    Ok(block)
+}
+
+/// Mines a template from a file without kaspad and writes the solved block, for the `solve` subcommand.
+pub async fn run(opt: &SolveOpt) -> Result<(), Error> {
+    let template = message_file::read_file(opt.template.as_deref())?;
+    let block = template_block(&template, opt.format)
+        .map_err(|e| format!("Failed parsing {}: {}", message_file::name(opt.template.as_deref()), e))?;
+    let block = solve(block, opt.bits, opt.num_threads).await?;
+    message_file::write(opt.output.as_deref(), opt.protobuf, &block)
+}
+
+/// Mines `block` with the miner threads, against the target of `bits` if given instead of the header's.
+async fn solve(block: RpcBlock, bits: Option<u32>, num_threads: Option<u16>) -> Result<RpcBlock, Error> {
+    let header = block.header.as_ref().expect("template_block checks there's a header");
+    let bits = bits.unwrap_or(header.bits);
+    info!(
+        "Solving the template with bits {:#010x}, {:.0} hashes are expected",
+        bits,
+        target::expected_hashes(target::u256_from_compact_target(bits))
+    );
+
+    let (send_channel, mut recv_channel) = mpsc::channel(1);
+    let shutdown = ShutdownHandler(Arc::new(AtomicBool::new(false)));
+    let _shutdown_when_dropped = shutdown.arm();
+    let (_num_threads, num_threads_rx) = watch::channel(get_num_cpus(num_threads));
+    let mut miner_manager = MinerManager::new(
+        send_channel,
+        Arc::new(Mutex::new(Submissions::with_limit(1))),
+        Arc::new(SessionStats::default()),
+        num_threads_rx,
+        MinerConfig { target_bits: Some(bits), ..Default::default() },
+        shutdown,
+    );
+    miner_manager.process_block(Some(block), PAY_ADDRESS);
+
+    // The miner threads submit the block they find to what would be kaspad
+    let message = tokio::select! {
+        message = recv_channel.recv() => message,
+        _ = tokio::signal::ctrl_c() => return Err("Interrupted before the template was solved".into()),
+    };
+    drop(miner_manager);
+    let Some(KaspadMessage {
+        payload: Some(Payload::SubmitBlockRequest(SubmitBlockRequestMessage { block: Some(block), .. })),
+    }) = message
+    else {
+        return Err("The miner threads stopped without solving the template".into());
+    };
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        message_file::MessageFormat,
+        pow,
+        proto::{GetBlockTemplateResponseMessage, RpcBlock, RpcBlockHeader, RpcError},
+        solve::{solve, template_block},
+        target,
+    };
+    use prost::Message;
+    use serde::Serialize;
+
+    fn encode<M: Message + Serialize>(message: &M, format: MessageFormat) -> Vec<u8> {
+        match format {
+            MessageFormat::Protobuf => message.encode_to_vec(),
+            _ => serde_json::to_vec(message).unwrap(),
+        }
+    }
+
+    fn block() -> RpcBlock {
+        RpcBlock {
+            header: Some(RpcBlockHeader {
+                version: 1,
+                hash_merkle_root: "11".repeat(32),
+                accepted_id_merkle_root: "22".repeat(32),
+                utxo_commitment: "33".repeat(32),
+                nonce: 5,
+                blue_work: "ab".to_string(),
+                pruning_point: "44".repeat(32),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_template_block() {
+        let block = block();
+        let template = GetBlockTemplateResponseMessage { block: Some(block.clone()), is_synced: true, error: None };
+        for format in [MessageFormat::Auto, MessageFormat::Json, MessageFormat::Protobuf] {
+            assert_eq!(template_block(&encode(&block, format), format).unwrap(), block);
+            assert_eq!(template_block(&encode(&template, format), format).unwrap(), block);
+        }
+
+        let error = GetBlockTemplateResponseMessage {
+            error: Some(RpcError { message: "not synced".to_string() }),
+            ..Default::default()
+        };
+        assert!(template_block(&error.encode_to_vec(), MessageFormat::Protobuf).is_err());
+        let mut invalid = block.clone();
+        invalid.header.as_mut().unwrap().utxo_commitment = "zz".to_string();
+        assert!(template_block(&encode(&invalid, MessageFormat::Json), MessageFormat::Auto).is_err());
+        assert!(template_block(b"{}", MessageFormat::Auto).is_err());
+    }
+
+    #[test]
+    fn test_solve_bits() {
+        let mut block = block();
+        // Far too hard to solve in a test without mining against the easier bits
+        block.header.as_mut().unwrap().bits = 0x1d00ffff;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let solved = runtime.block_on(solve(block.clone(), Some(0x207fffff), Some(1))).unwrap();
+        let header = solved.header.as_ref().unwrap();
+        assert_eq!(header.bits, 0x1d00ffff);
+        assert!(pow::calculate_pow(header).unwrap() <= target::u256_from_compact_target(0x207fffff));
+        // Only the nonce changed
+        let mut unsolved = solved.clone();
+        unsolved.header.as_mut().unwrap().nonce = block.header.as_ref().unwrap().nonce;
+        assert_eq!(unsolved, block);
+    }
+}
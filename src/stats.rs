@@ -0,0 +1,306 @@
+use crate::hashrate::HashrateAverages;
+use log::info;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+
+/// How often the session statistics are logged by default
+pub const STATS_RATE: Duration = Duration::from_secs(5 * 60);
+
+/// How many of the latest templates are remembered, for blocks found on a template that was just replaced
+const RECENT_TEMPLATES: usize = 16;
+
+/// The blocks found over the whole run of the miner, across reconnections to kaspad, against the ones expected for the hashes tried.
+#[derive(Debug)]
+pub struct SessionStats {
+    /// Hashes tried since they were last accounted for
+    hashes: AtomicU64,
+    /// The next template's id, unique across reconnections so blocks are credited to the right template
+    template_ids: AtomicUsize,
+    inner: Mutex<Inner>,
+    hashrate: Mutex<HashrateAverages>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    started: Instant,
+    templates: u64,
+    /// The expected hashes per block on the current template
+    block_work: f64,
+    /// The latest templates' ids and payout addresses
+    recent_templates: VecDeque<(usize, String)>,
+    hashes: u64,
+    /// How many blocks the hashes tried should have found
+    expected_blocks: f64,
+    /// The expected blocks since the last block was found, i.e. the effort put into the next one
+    effort: f64,
+    blocks: u64,
+    first_block: Option<Instant>,
+    last_block: Option<Instant>,
+    blocks_per_address: BTreeMap<String, u64>,
+    accepted: u64,
+    rejected: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub elapsed: Duration,
+    pub templates: u64,
+    pub hashes: u64,
+    pub blocks: u64,
+    pub expected_blocks: f64,
+    /// The effort put into the next block so far, 1 is the expected hashes of a block
+    pub effort: f64,
+    pub average_interval: Option<Duration>,
+    pub since_last_block: Option<Duration>,
+    pub blocks_per_address: BTreeMap<String, u64>,
+    /// Blocks kaspad responded to as accepted
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+impl Summary {
+    /// The blocks found relative to the expected amount, above 1 is better than average
+    pub fn luck(&self) -> Option<f64> {
+        (self.expected_blocks > 0.0).then(|| self.blocks as f64 / self.expected_blocks)
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self {
+            hashes: AtomicU64::new(0),
+            template_ids: AtomicUsize::new(0),
+            inner: Mutex::new(Inner {
+                started: Instant::now(),
+                templates: 0,
+                // No hashes are tried before the first template
+                block_work: f64::INFINITY,
+                recent_templates: VecDeque::with_capacity(RECENT_TEMPLATES),
+                hashes: 0,
+                expected_blocks: 0.0,
+                effort: 0.0,
+                blocks: 0,
+                first_block: None,
+                last_block: None,
+                blocks_per_address: BTreeMap::new(),
+                accepted: 0,
+                rejected: 0,
+            }),
+            hashrate: Mutex::default(),
+        }
+    }
+}
+
+impl SessionStats {
+    #[inline]
+    pub fn add_hashes(&self, hashes: u64) {
+        self.hashes.fetch_add(hashes, Ordering::Relaxed);
+    }
+
+    /// A new id for a template, unique within the session.
+    pub fn next_template_id(&self) -> usize {
+        // Relaxed ordering here means there's no promise that the counter will always go up, but the id will always be unique
+        self.template_ids.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Accounts for the hashes tried so far, on the current template.
+    fn take_hashes(&self, inner: &mut Inner) {
+        let hashes = self.hashes.swap(0, Ordering::Relaxed);
+        let expected = hashes as f64 / inner.block_work;
+        inner.hashes += hashes;
+        inner.expected_blocks += expected;
+        inner.effort += expected;
+    }
+
+    pub fn new_template(&self, id: usize, block_work: f64, pay_address: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.take_hashes(&mut inner);
+        inner.templates += 1;
+        inner.block_work = block_work;
+        if inner.recent_templates.len() == RECENT_TEMPLATES {
+            inner.recent_templates.pop_front();
+        }
+        inner.recent_templates.push_back((id, pay_address.to_string()));
+    }
+
+    /// The payout address of one of the latest templates
+    pub fn pay_address(&self, template_id: usize) -> Option<String> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        Self::find_pay_address(&inner, template_id)
+    }
+
+    fn find_pay_address(inner: &Inner, template_id: usize) -> Option<String> {
+        // The latest template with this id, in case it was reused
+        inner.recent_templates.iter().rfind(|(id, _)| *id == template_id).map(|(_, pay_address)| pay_address.clone())
+    }
+
+    /// Records a block found on the template with this id.
+    pub fn block_found(&self, template_id: usize, now: Instant) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.take_hashes(&mut inner);
+        let pay_address = Self::find_pay_address(&inner, template_id).unwrap_or_else(|| "unknown".to_string());
+        *inner.blocks_per_address.entry(pay_address).or_default() += 1;
+        inner.blocks += 1;
+        inner.effort = 0.0;
+        inner.first_block.get_or_insert(now);
+        inner.last_block = Some(now);
+    }
+
+    /// Records kaspad's response to a submitted block.
+    pub fn block_response(&self, accepted: bool) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if accepted {
+            inner.accepted += 1;
+        } else {
+            inner.rejected += 1;
+        }
+    }
+
+    /// Adds a sample of the hashrate to the session's averages, returning them.
+    pub fn add_hashrate(&self, hashes: u64, elapsed: Duration) -> HashrateAverages {
+        let mut hashrate = self.hashrate.lock().unwrap_or_else(|e| e.into_inner());
+        hashrate.add(hashes, elapsed);
+        hashrate.clone()
+    }
+
+    pub fn summary(&self, now: Instant) -> Summary {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.take_hashes(&mut inner);
+        let average_interval = match (inner.first_block, inner.last_block) {
+            (Some(first), Some(last)) if inner.blocks > 1 => Some((last - first) / (inner.blocks - 1) as u32),
+            _ => None,
+        };
+        Summary {
+            elapsed: now.saturating_duration_since(inner.started),
+            templates: inner.templates,
+            hashes: inner.hashes,
+            blocks: inner.blocks,
+            expected_blocks: inner.expected_blocks,
+            effort: inner.effort,
+            average_interval,
+            since_last_block: inner.last_block.map(|last| now.saturating_duration_since(last)),
+            blocks_per_address: inner.blocks_per_address.clone(),
+            accepted: inner.accepted,
+            rejected: inner.rejected,
+        }
+    }
+
+    pub fn log_summary(&self) {
+        let summary = self.summary(Instant::now());
+        let luck = summary.luck().map(|luck| format!(", luck: {:.0}%", luck * 100.0)).unwrap_or_default();
+        info!(
+            "Session: found {} blocks in {}, {:.2} were expected from {} hashes on {} templates{}",
+            summary.blocks,
+            format_duration(summary.elapsed),
+            summary.expected_blocks,
+            summary.hashes,
+            summary.templates,
+            luck
+        );
+        info!("Session: kaspad accepted {} blocks and rejected {}", summary.accepted, summary.rejected);
+        if let (Some(average), Some(since_last)) = (summary.average_interval, summary.since_last_block) {
+            info!(
+                "Session: a block every {} on average, the last one {} ago",
+                format_duration(average),
+                format_duration(since_last)
+            );
+        }
+        info!("Session: effort on the next block is {:.0}%", summary.effort * 100.0);
+        for (pay_address, blocks) in &summary.blocks_per_address {
+            info!("Session: {} blocks paid to {}", blocks, pay_address);
+        }
+    }
+
+    /// Logs the summary every `interval`, once there's a template to mine.
+    pub async fn log_summaries(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if self.inner.lock().unwrap_or_else(|e| e.into_inner()).templates != 0 {
+                self.log_summary();
+            }
+        }
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{format_duration, SessionStats};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_session_stats() {
+        let stats = SessionStats::default();
+        let start = Instant::now();
+        stats.new_template(0, 1000.0, "kaspa:miner");
+        stats.add_hashes(500);
+        stats.new_template(1, 2000.0, "kaspa:devfund");
+        stats.add_hashes(1000);
+        stats.block_found(1, start);
+        // A block found on the previous template after it was replaced
+        stats.new_template(2, 2000.0, "kaspa:miner");
+        stats.block_found(1, start + Duration::from_secs(30));
+        stats.add_hashes(3000);
+        stats.block_response(true);
+        stats.block_response(false);
+        stats.block_response(true);
+
+        let summary = stats.summary(start + Duration::from_secs(40));
+        assert_eq!(summary.templates, 3);
+        assert_eq!(summary.hashes, 4500);
+        assert_eq!(summary.blocks, 2);
+        assert_eq!(summary.expected_blocks, 2.5);
+        assert_eq!(summary.luck(), Some(0.8));
+        assert_eq!(summary.effort, 1.5);
+        assert_eq!(summary.average_interval, Some(Duration::from_secs(30)));
+        assert_eq!(summary.since_last_block, Some(Duration::from_secs(10)));
+        assert_eq!(summary.blocks_per_address.get("kaspa:devfund"), Some(&2));
+        assert_eq!(summary.blocks_per_address.get("kaspa:miner"), None);
+        assert_eq!((summary.accepted, summary.rejected), (2, 1));
+
+        for id in 3..20 {
+            stats.new_template(id, 1.0, "kaspa:miner");
+        }
+        stats.block_found(1, start);
+        assert_eq!(stats.summary(start).blocks_per_address.get("unknown"), Some(&1));
+        assert_eq!(stats.pay_address(19).as_deref(), Some("kaspa:miner"));
+        assert_eq!(stats.pay_address(1), None);
+
+        // A reused id pays to its latest template
+        stats.new_template(19, 1.0, "kaspa:devfund");
+        assert_eq!(stats.pay_address(19).as_deref(), Some("kaspa:devfund"));
+        stats.block_found(19, start);
+        assert_eq!(stats.summary(start).blocks_per_address.get("kaspa:devfund"), Some(&3));
+
+        // Which doesn't happen across reconnections, since the ids come from the session
+        let ids: Vec<_> = (0..3).map(|_| stats.next_template_id()).collect();
+        assert_eq!(ids, [0, 1, 2]);
+
+        assert_eq!(SessionStats::default().summary(Instant::now()).luck(), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(5900)), "5s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m05s");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 61)), "3h01m01s");
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    proto::{KaspadMessage, RpcBlock},
+    Error, Hash,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::Sender;
+
+pub type SharedSubmissions = Arc<Mutex<Submissions>>;
+
+/// The blocks submitted to kaspad, kaspad responds to them in the same order they were submitted in.
+#[derive(Debug, Default)]
+pub struct Submissions {
+    pending: VecDeque<Hash>,
+    accepted: Vec<Hash>,
+    /// Stop submitting blocks once this many were accepted
+    limit: Option<usize>,
+}
+
+impl Submissions {
+    pub fn with_limit(limit: usize) -> Self {
+        Self { limit: Some(limit), ..Default::default() }
+    }
+
+    /// Adds `hash` as pending, unless enough blocks are accepted or pending to reach the limit.
+    fn reserve(&mut self, hash: Hash) -> bool {
+        if self.limit.is_some_and(|limit| self.accepted.len() + self.pending.len() >= limit) {
+            return false;
+        }
+        self.pending.push_back(hash);
+        true
+    }
+
+    /// Matches kaspad's response to the oldest pending block, returning its hash.
+    pub fn respond(&mut self, accepted: bool) -> Option<Hash> {
+        let hash = self.pending.pop_front()?;
+        if accepted {
+            self.accepted.push(hash);
+        }
+        Some(hash)
+    }
+
+    /// Responses to blocks submitted on a lost connection will never arrive
+    pub fn disconnected(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn accepted(&self) -> &[Hash] {
+        &self.accepted
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.limit.is_some_and(|limit| self.accepted.len() >= limit)
+    }
+}
+
+/// Submits `block`, unless enough blocks are accepted or pending to reach the limit. Returns whether it was submitted.
+///
+/// Only the threads submitting blocks take `send_order`, which keeps the pending blocks in the order kaspad gets them
+/// without holding the `submissions` lock its responses need while waiting for room in the channel.
+pub fn submit(
+    submissions: &Mutex<Submissions>,
+    send_order: &Mutex<()>,
+    send_channel: &Sender<KaspadMessage>,
+    block: RpcBlock,
+    hash: Hash,
+) -> Result<bool, Error> {
+    let _send_order = send_order.lock().unwrap_or_else(|e| e.into_inner());
+    if !submissions.lock().unwrap_or_else(|e| e.into_inner()).reserve(hash) {
+        return Ok(false);
+    }
+    if let Err(e) = send_channel.blocking_send(KaspadMessage::submit_block(block, false)) {
+        // Nothing was reserved after it, since we're still holding `send_order`
+        submissions.lock().unwrap_or_else(|e| e.into_inner()).pending.pop_back();
+        return Err(e.into());
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        proto::{kaspad_message::Payload, RpcBlock},
+        submissions::{submit, Submissions},
+        Hash,
+    };
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_submissions() {
+        let (send_channel, mut recv) = mpsc::channel(20);
+        let submissions = Mutex::new(Submissions::with_limit(2));
+        let send_order = Mutex::new(());
+        let submit = |hash| submit(&submissions, &send_order, &send_channel, RpcBlock::default(), hash).unwrap();
+        let hash = Hash::from_u64;
+        assert!(submit(hash(1)));
+        assert!(submit(hash(2)));
+        // Both could be accepted, so a third one might be one too many
+        assert!(!submit(hash(3)));
+        assert!(matches!(recv.try_recv().unwrap().payload, Some(Payload::SubmitBlockRequest(_))));
+        assert!(recv.try_recv().is_ok());
+        assert!(recv.try_recv().is_err());
+
+        assert_eq!(submissions.lock().unwrap().respond(false), Some(hash(1)));
+        assert!(submit(hash(4)));
+        assert_eq!(submissions.lock().unwrap().respond(true), Some(hash(2)));
+        assert!(!submissions.lock().unwrap().is_done());
+
+        submissions.lock().unwrap().disconnected();
+        assert_eq!(submissions.lock().unwrap().respond(true), None);
+        assert!(submit(hash(5)));
+        assert_eq!(submissions.lock().unwrap().respond(true), Some(hash(5)));
+        assert!(submissions.lock().unwrap().is_done());
+        assert_eq!(submissions.lock().unwrap().accepted(), &[hash(2), hash(5)]);
+
+        let unlimited = Mutex::new(Submissions::default());
+        assert!((0..10).all(|i| crate::submissions::submit(
+            &unlimited,
+            &send_order,
+            &send_channel,
+            RpcBlock::default(),
+            hash(i)
+        )
+        .is_ok_and(|submitted| submitted)));
+        assert!(!unlimited.lock().unwrap().is_done());
+
+        // A block that can't be sent isn't left pending
+        drop(recv);
+        assert!(
+            crate::submissions::submit(&unlimited, &send_order, &send_channel, RpcBlock::default(), hash(10)).is_err()
+        );
+        assert_eq!(unlimited.lock().unwrap().pending.len(), 10);
+    }
+}
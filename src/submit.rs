@@ -0,0 +1,56 @@
+use crate::{
+    cli::SubmitOpt,
+    message_file,
+    proto::{kaspad_message::Payload, rpc_client::RpcClient, KaspadMessage, RpcBlock},
+    Error,
+};
+use log::info;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Submits blocks from files to kaspad and prints its response to each, for the `submit` subcommand.
+pub async fn run(opt: &SubmitOpt) -> Result<(), Error> {
+    // Read them all first, so a bad file doesn't leave the blocks half submitted
+    let blocks = opt
+        .blocks
+        .iter()
+        .map(|path| {
+            let block: RpcBlock = message_file::read(Some(path), opt.format)?;
+            let hash = block.block_hash().map_err(|e| format!("Can't submit {}: {}", path.display(), e))?;
+            Ok((path, block, hash))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let address = opt.kaspad_address()?;
+    info!("Kaspad address: {}", address);
+    let mut client = RpcClient::connect(address).await?;
+    let (send_channel, recv) = mpsc::channel(blocks.len());
+    for (_, block, _) in &blocks {
+        send_channel.send(KaspadMessage::submit_block(block.clone(), opt.allow_non_daa_blocks)).await?;
+    }
+    let mut stream = client.message_stream(ReceiverStream::new(recv)).await?.into_inner();
+
+    // Kaspad responds to the blocks in the order they were submitted
+    let mut rejected = 0;
+    for (path, _, hash) in &blocks {
+        let response = loop {
+            match stream.message().await? {
+                Some(KaspadMessage { payload: Some(Payload::SubmitBlockResponse(response)) }) => break response,
+                Some(_) => continue,
+                None => return Err("Kaspad closed the connection before responding to every block".into()),
+            }
+        };
+        let reject_reason = response.reject_reason().as_str_name();
+        match response.error {
+            None => println!("{}: block {:x} accepted", path.display(), hash),
+            Some(error) => {
+                rejected += 1;
+                println!("{}: block {:x} rejected ({}): {}", path.display(), hash, reject_reason, error.message);
+            }
+        }
+    }
+    if rejected != 0 {
+        return Err(format!("{} of {} blocks were rejected", rejected, blocks.len()).into());
+    }
+    Ok(())
+}
@@ -43,7 +43,8 @@ impl<T> WatchSwap<T> {
         }
     }
 
-    fn wake_up_threads(&self) {
+    /// Wakes up the threads waiting for a change, e.g. to check their [`Self::wait_for_change_or`] condition.
+    pub fn wake_up_threads(&self) {
         // Make sure no receiver is "almost" waiting (holding the lock but hasn't entered the Condvar yet)
         let _lock = self.shared.wait_mutex.lock();
         self.shared.wait_cv.notify_all();
@@ -78,11 +79,20 @@ impl<T> WatchSwap<T> {
         old
     }
 
+    #[cfg(test)]
     pub fn wait_for_change(&mut self) -> impl Deref<Target = Option<Arc<T>>> + '_ {
+        self.wait_for_change_or(|| false).expect("Waiting is never stopped")
+    }
+
+    /// Waits until the value changes, or gives up once `stop` returns true, checked whenever the threads are woken up.
+    pub fn wait_for_change_or(&mut self, stop: impl Fn() -> bool) -> Option<impl Deref<Target = Option<Arc<T>>> + '_> {
         let mut guard = self.shared.wait_mutex.lock();
         loop {
             if Self::get_changed_inner(&mut self.cached, &self.shared.inner) {
-                return &self.cached;
+                return Some(&self.cached);
+            }
+            if stop() {
+                return None;
             }
             guard = self.shared.wait_cv.wait(guard);
         }
@@ -110,6 +120,8 @@ mod sync {
     #[cfg(all(feature = "shuttle", test))]
     pub use shuttle::thread;
 
+    #[cfg(all(not(feature = "shuttle"), test))]
+    pub use std::sync::atomic::{AtomicBool, Ordering};
     #[cfg(not(feature = "shuttle"))]
     pub use std::sync::Arc;
     #[cfg(all(not(feature = "shuttle"), test))]
@@ -161,7 +173,7 @@ mod sync {
 #[cfg(test)]
 mod tests {
     use crate::swap_rust::{
-        sync::{thread, Arc, Barrier},
+        sync::{thread, Arc, AtomicBool, Barrier, Ordering},
         WatchSwap,
     };
 
@@ -257,4 +269,24 @@ mod tests {
             true,
         )
     }
+
+    #[test]
+    fn test_waiting_stopped() {
+        multi_test_runner(
+            || {
+                let (rx, mut tx) = channel("One");
+                assert_eq!(tx.get_changed().as_deref().map(|a| a.as_deref()), Some(Some(&"One")));
+                let stop = Arc::new(AtomicBool::new(false));
+                let stop_clone = Arc::clone(&stop);
+                let handle = thread::spawn(move || {
+                    assert!(tx.wait_for_change_or(|| stop_clone.load(Ordering::Acquire)).is_none());
+                });
+                stop.store(true, Ordering::Release);
+                rx.wake_up_threads();
+
+                handle.join().unwrap();
+            },
+            true,
+        )
+    }
 }
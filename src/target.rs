@@ -1,6 +1,7 @@
-use core::cmp::Ordering;
-use std::fmt;
+//! Targets and the 256 bit integers PoW is compared with.
+use core::{cmp::Ordering, fmt};
 
+/// Decodes the compact `bits` of a header to its target.
 pub fn u256_from_compact_target(bits: u32) -> Uint256 {
     // This is a floating-point "compact" encoding originally used by
     // OpenSSL, which satoshi put into consensus code, so we're stuck
@@ -23,6 +24,21 @@ pub fn u256_from_compact_target(bits: u32) -> Uint256 {
     }
 }
 
+// `powi` needs `std`, so these are built from their exponent bits
+const TWO_POW_64: f64 = f64::from_bits((1023 + 64) << 52);
+const TWO_POW_255: f64 = f64::from_bits((1023 + 255) << 52);
+const TWO_POW_256: f64 = f64::from_bits((1023 + 256) << 52);
+
+/// The expected amount of hashes until one is less or equal than `target`
+pub fn expected_hashes(target: Uint256) -> f64 {
+    TWO_POW_256 / (target.as_f64() + 1.0)
+}
+
+/// The difficulty of `target` as kaspad reports it, relative to the easiest target of 2^255 - 1
+pub fn difficulty(target: Uint256) -> f64 {
+    TWO_POW_255 / target.as_f64()
+}
+
 /// Little-endian large integer type
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug)]
 pub struct Uint256(pub [u64; 4]);
@@ -59,6 +75,27 @@ impl Uint256 {
         out.chunks_exact_mut(8).zip(self.0).for_each(|(bytes, word)| bytes.copy_from_slice(&word.to_le_bytes()));
         out
     }
+
+    /// Multiplies by `rhs`, returning the maximum value on overflow
+    pub fn saturating_mul_u64(self, rhs: u64) -> Uint256 {
+        let mut ret = [0u64; 4];
+        let mut carry = 0u128;
+        for (out, &word) in ret.iter_mut().zip(&self.0) {
+            let product = u128::from(word) * u128::from(rhs) + carry;
+            *out = product as u64;
+            carry = product >> 64;
+        }
+        if carry == 0 {
+            Uint256(ret)
+        } else {
+            Uint256([u64::MAX; 4])
+        }
+    }
+
+    /// The closest float, losing all but the highest 53 bits
+    pub fn as_f64(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, &word| acc * TWO_POW_64 + word as f64)
+    }
 }
 
 impl fmt::LowerHex for Uint256 {
@@ -105,3 +142,32 @@ impl core::ops::Shl<usize> for Uint256 {
         Uint256(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::target::{difficulty, expected_hashes, u256_from_compact_target, Uint256};
+
+    #[test]
+    fn test_saturating_mul() {
+        assert_eq!(Uint256::from_u64(3).saturating_mul_u64(5), Uint256::from_u64(15));
+        assert_eq!(Uint256::from_u64(u64::MAX).saturating_mul_u64(2), Uint256::new([u64::MAX - 1, 1, 0, 0]));
+        assert_eq!(Uint256::new([0, 0, 0, 1 << 63]).saturating_mul_u64(2), Uint256::new([u64::MAX; 4]));
+        assert_eq!(Uint256::new([7, 0, 0, 1]).saturating_mul_u64(0), Uint256::default());
+    }
+
+    #[test]
+    fn test_expected_hashes() {
+        assert_eq!(Uint256::new([0, 1, 0, 0]).as_f64(), 2f64.powi(64));
+        // A target of 2^255 - 1 is met by half the hashes
+        assert_eq!(expected_hashes(Uint256::new([u64::MAX, u64::MAX, u64::MAX, u64::MAX >> 1])), 2.0);
+        // 0x7fffff << 216, just under 2^239
+        let target = u256_from_compact_target(0x1e7fffff);
+        assert!((expected_hashes(target) / 2f64.powi(17) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_difficulty() {
+        assert_eq!(difficulty(Uint256::new([u64::MAX, u64::MAX, u64::MAX, u64::MAX >> 1])), 1.0);
+        assert_eq!(difficulty(Uint256::new([0, 0, 0, 1 << 59])), 16.0);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::block_rate::BlockRate;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub enum Throttle {
+    /// Sleep after every hash
+    Sleep(Duration),
+    /// Only hash for this fraction of the time
+    CpuUsage(f64),
+    /// Cap the hashrate of all the threads sharing the bucket together
+    MaxHashrate(Arc<TokenBucket>),
+    /// Cap the hashrate to find blocks at an interval, or pause after every block on easy templates
+    BlockRate(Arc<BlockRate>),
+}
+
+/// The per thread state of a [`Throttle`]
+pub struct Throttler {
+    throttle: Throttle,
+    cycle_start: Instant,
+}
+
+impl Throttler {
+    /// How long a thread hashes and sleeps for in a single duty cycle, at most
+    const DUTY_CYCLE: Duration = Duration::from_millis(100);
+    /// How long a thread sleeps before checking if it should stop, at most
+    const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+    pub fn new(throttle: Throttle) -> Self {
+        Self { throttle, cycle_start: Instant::now() }
+    }
+
+    #[inline(always)]
+    pub fn after_hash(&mut self) {
+        if let Throttle::Sleep(duration) = self.throttle {
+            std::thread::sleep(duration);
+        }
+    }
+
+    /// Called after every batch of `hashes` hashes, sleeping in slices until `stop` returns true.
+    #[inline]
+    pub fn checkpoint(&mut self, hashes: u64, mut stop: impl FnMut() -> bool) {
+        match &self.throttle {
+            Throttle::Sleep(_) => {}
+            Throttle::CpuUsage(usage) => {
+                let busy = self.cycle_start.elapsed();
+                if busy >= Self::DUTY_CYCLE.mul_f64(*usage) {
+                    // Sleeping relative to how long we actually hashed keeps the ratio even when a batch overshoots.
+                    let wake = Instant::now() + busy.mul_f64(1.0 / usage - 1.0);
+                    let mut now = Instant::now();
+                    while now < wake && !stop() {
+                        std::thread::sleep((wake - now).min(Self::SLEEP_SLICE));
+                        now = Instant::now();
+                    }
+                    self.cycle_start = Instant::now();
+                }
+            }
+            Throttle::MaxHashrate(bucket) => Self::wait(|hashes| bucket.take(hashes, Instant::now()), hashes, stop),
+            Throttle::BlockRate(block_rate) => {
+                Self::wait(|hashes| block_rate.wait(hashes, Instant::now()), hashes, stop)
+            }
+        }
+    }
+
+    /// Sleeps in slices for as long as `wait` asks after taking `hashes`, or until `stop` returns true.
+    fn wait(wait: impl Fn(u64) -> Duration, hashes: u64, mut stop: impl FnMut() -> bool) {
+        // Asking again after every slice follows changes of the rate, and the other threads' hashes
+        let mut duration = wait(hashes);
+        while !duration.is_zero() && !stop() {
+            std::thread::sleep(duration.min(Self::SLEEP_SLICE));
+            duration = wait(0);
+        }
+    }
+}
+
+/// A token bucket holding one token per hash, refilled at the maximum hashrate.
+#[derive(Debug)]
+pub struct TokenBucket {
+    // The bits of an f64, so the rate can be changed while the threads are hashing
+    rate: AtomicU64,
+    // (tokens, last refill), tokens go negative when hashes are taken on credit
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// How long of an idle period can be made up for by hashing faster afterwards
+    const BURST: Duration = Duration::from_secs(1);
+    /// The longest wait [`take`](Self::take) returns, a tiny rate could ask for longer than a `Duration` holds
+    pub const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(rate: f64) -> Self {
+        Self { rate: AtomicU64::new(rate.to_bits()), state: Mutex::new((0.0, Instant::now())) }
+    }
+
+    pub fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    /// Changes the maximum hashrate, hashes already taken on credit are paid back at the new rate.
+    pub fn set_rate(&self, rate: f64) {
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Takes `hashes` tokens, returning how long to sleep until they would have been available, up to [`MAX_WAIT`](Self::MAX_WAIT).
+    pub fn take(&self, hashes: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, last_refill) = &mut *state;
+        let rate = self.rate();
+        let refill = now.saturating_duration_since(*last_refill).as_secs_f64() * rate;
+        *tokens = (*tokens + refill).min(rate * Self::BURST.as_secs_f64()) - hashes as f64;
+        *last_refill = now.max(*last_refill);
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64(-*tokens / rate).map_or(Self::MAX_WAIT, |wait| wait.min(Self::MAX_WAIT))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block_rate::BlockRate,
+        throttle::{Throttle, Throttler, TokenBucket},
+    };
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_token_bucket() {
+        let bucket = TokenBucket::new(1000.0);
+        let start = bucket.state.lock().unwrap().1;
+        // Starts empty, so the first batch has to wait for its tokens
+        assert_eq!(bucket.take(100, start), Duration::from_millis(100));
+        // Other threads queue up behind it
+        assert_eq!(bucket.take(100, start), Duration::from_millis(200));
+        // Sleeping for it pays back the debt
+        assert_eq!(bucket.take(100, start + Duration::from_millis(200)), Duration::from_millis(100));
+        assert_eq!(bucket.take(0, start + Duration::from_millis(300)), Duration::ZERO);
+
+        // Idle time can only be made up for a second
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.take(1000, later), Duration::ZERO);
+        assert_eq!(bucket.take(500, later), Duration::from_millis(500));
+
+        // A clock that seems to go backwards doesn't refill
+        assert_eq!(bucket.take(0, start), Duration::from_millis(500));
+
+        // The debt is paid back at the new rate
+        bucket.set_rate(100.0);
+        assert_eq!(bucket.take(0, later), Duration::from_secs(5));
+
+        // Long waits are capped
+        bucket.set_rate(0.01);
+        assert_eq!(bucket.take(128, later), TokenBucket::MAX_WAIT);
+        bucket.set_rate(1e-19);
+        assert_eq!(bucket.take(128, later), TokenBucket::MAX_WAIT);
+    }
+
+    #[test]
+    fn test_throttler_stops() {
+        // A batch that would take a minute to pay back ends as soon as the thread has to stop
+        let bucket = Arc::new(TokenBucket::new(128.0 / 60.0));
+        let mut throttler = Throttler::new(Throttle::MaxHashrate(Arc::clone(&bucket)));
+        let start = Instant::now();
+        let mut checks = 0;
+        throttler.checkpoint(128, || {
+            checks += 1;
+            checks > 2
+        });
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(checks, 3);
+
+        // Pausing until the next block on an easy template too
+        let block_rate = Arc::new(BlockRate::new(Duration::from_secs(60)));
+        block_rate.set_block_work(2.0);
+        let mut throttler = Throttler::new(block_rate.throttle());
+        let start = Instant::now();
+        throttler.checkpoint(128, || panic!("Hashing goes on until a block is found"));
+        assert!(block_rate.claim(Instant::now()));
+        let mut checks = 0;
+        throttler.checkpoint(128, || {
+            checks += 1;
+            checks > 2
+        });
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(checks, 3);
+
+        let mut throttler = Throttler::new(Throttle::CpuUsage(0.001));
+        std::thread::sleep(Duration::from_millis(1));
+        let start = Instant::now();
+        throttler.checkpoint(128, || true);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
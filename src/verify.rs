@@ -0,0 +1,198 @@
+use crate::{
+    cli::{VerifyOpt, VerifyP2pOpt},
+    message_file,
+    pow::{Header, HeaderError},
+    proto::{kaspad_message::Payload, BlockHeader, BlockMessage, KaspadMessage, RpcBlock, RpcBlockHeader},
+    target, Error,
+};
+use clap::ValueEnum;
+use prost::Message;
+use serde::Serialize;
+
+/// How p2p messages are stored in a dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum P2pFormat {
+    /// The gRPC messages of a p2p stream, each a `KaspadMessage` after a compression flag and a 4 byte big-endian length,
+    /// e.g. the reassembled HTTP/2 data of a pcap
+    Grpc,
+    /// A single protobuf `BlockMessage`
+    Block,
+}
+
+/// The hashes of a header, for debugging blocks kaspad rejected
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub block_hash: String,
+    pub pre_pow_hash: String,
+    pub pow: String,
+    pub bits: u32,
+    pub target: String,
+    pub difficulty: f64,
+    /// Whether the PoW isn't above the target
+    pub valid: bool,
+}
+
+impl Report {
+    pub fn new(header: &Header) -> Self {
+        let pow = header.calculate_pow();
+        let target = header.target();
+        Self {
+            block_hash: format!("{:x}", header.block_hash()),
+            pre_pow_hash: format!("{:x}", header.pre_pow_hash()),
+            pow: format!("{:x}", pow),
+            bits: header.bits,
+            target: format!("{:x}", target),
+            difficulty: target::difficulty(target),
+            valid: pow <= target,
+        }
+    }
+
+    fn print(&self, json: bool) -> Result<(), Error> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+        println!("Block hash:   {}", self.block_hash);
+        println!("Pre-PoW hash: {}", self.pre_pow_hash);
+        println!("PoW:          {}", self.pow);
+        println!("Bits:         {:#010x}", self.bits);
+        println!("Target:       {}", self.target);
+        println!("Difficulty:   {}", self.difficulty);
+        println!("Valid:        {}", if self.valid { "yes" } else { "no" });
+        Ok(())
+    }
+}
+
+/// A header of a p2p dump, with its report or why it can't be hashed
+#[derive(Debug, Serialize)]
+struct P2pReport {
+    file: String,
+    index: usize,
+    #[serde(flatten)]
+    report: Option<Report>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Prints the hashes of an `RpcBlock`, failing if its PoW isn't valid, for the `verify-block` subcommand.
+pub fn verify_block(opt: &VerifyOpt) -> Result<(), Error> {
+    let block: RpcBlock = message_file::read(opt.file.as_deref(), opt.format)?;
+    let report = Report::new(&Header::try_from(block.header.as_ref().ok_or(HeaderError::Missing)?)?);
+    report.print(opt.json)?;
+    if !report.valid {
+        return Err("The block's PoW is above its target".into());
+    }
+    Ok(())
+}
+
+/// Prints the hashes of an `RpcBlockHeader`, for the `hash-header` subcommand.
+pub fn hash_header(opt: &VerifyOpt) -> Result<(), Error> {
+    let header: RpcBlockHeader = message_file::read(opt.file.as_deref(), opt.format)?;
+    Report::new(&Header::try_from(&header)?).print(opt.json)
+}
+
+/// Checks the PoW of every block header in dumps of kaspad's p2p messages, for the `verify-p2p` subcommand.
+pub fn verify_p2p(opt: &VerifyP2pOpt) -> Result<(), Error> {
+    let files =
+        if opt.files.is_empty() { vec![None] } else { opt.files.iter().map(|file| Some(file.as_path())).collect() };
+    let (mut reports, mut total, mut invalid) = (Vec::new(), 0, 0);
+    for file in files {
+        let name = message_file::name(file);
+        let headers = p2p_headers(&message_file::read_file(file)?, opt.format)
+            .map_err(|e| format!("Failed parsing {}: {}", name, e))?;
+        total += headers.len();
+        for (index, header) in headers.iter().enumerate() {
+            let (report, error) = match Header::try_from(header) {
+                Ok(header) => {
+                    let report = Report::new(&header);
+                    if !report.valid {
+                        invalid += 1;
+                    }
+                    if !opt.json {
+                        let result = if report.valid { "valid" } else { "PoW above the target" };
+                        println!("{} header {}: block {} {}", name, index, report.block_hash, result);
+                    }
+                    (Some(report), None)
+                }
+                Err(e) => {
+                    invalid += 1;
+                    if !opt.json {
+                        println!("{} header {}: {}", name, index, e);
+                    }
+                    (None, Some(e.to_string()))
+                }
+            };
+            reports.push(P2pReport { file: name.clone(), index, report, error });
+        }
+    }
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+    match (total, invalid) {
+        (0, _) => Err("No block headers in the p2p messages".into()),
+        (_, 0) => Ok(()),
+        _ => Err(format!("{} of {} headers are invalid", invalid, total).into()),
+    }
+}
+
+/// The headers of the blocks in p2p messages, skipping the other messages.
+fn p2p_headers(mut bytes: &[u8], format: P2pFormat) -> Result<Vec<BlockHeader>, Error> {
+    if format == P2pFormat::Block {
+        return Ok(BlockMessage::decode(bytes)?.header.into_iter().collect());
+    }
+    let mut headers = Vec::new();
+    let block_header = |block: Option<BlockMessage>| block.and_then(|block| block.header);
+    while !bytes.is_empty() {
+        let [compressed, len @ ..] = *bytes.first_chunk::<5>().ok_or("Truncated gRPC message prefix")?;
+        if compressed != 0 {
+            return Err("Compressed gRPC messages aren't supported".into());
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        bytes = &bytes[5..];
+        if len > bytes.len() {
+            return Err("Truncated gRPC message".into());
+        }
+        let (message, rest) = bytes.split_at(len);
+        bytes = rest;
+        match KaspadMessage::decode(message)?.payload {
+            Some(Payload::Block(block) | Payload::IbdBlock(block)) => headers.extend(block_header(Some(block))),
+            Some(Payload::BlockWithTrustedData(block)) => headers.extend(block_header(block.block)),
+            Some(Payload::BlockHeaders(message)) => headers.extend(message.block_headers),
+            _ => {}
+        }
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        proto::{kaspad_message::Payload, BlockHeader, BlockHeadersMessage, BlockMessage, KaspadMessage, PingMessage},
+        verify::{p2p_headers, P2pFormat},
+    };
+    use prost::Message;
+
+    fn frame(payload: Payload) -> Vec<u8> {
+        let message = KaspadMessage { payload: Some(payload) }.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend((message.len() as u32).to_be_bytes());
+        frame.extend(message);
+        frame
+    }
+
+    #[test]
+    fn test_p2p_headers() {
+        let header = |nonce| BlockHeader { version: 1, nonce, ..Default::default() };
+        let block = BlockMessage { header: Some(header(1)), transactions: vec![] };
+        let mut dump = frame(Payload::Ping(PingMessage { nonce: 7 }));
+        dump.extend(frame(Payload::Block(block.clone())));
+        dump.extend(frame(Payload::BlockHeaders(BlockHeadersMessage { block_headers: vec![header(2), header(3)] })));
+        assert_eq!(p2p_headers(&dump, P2pFormat::Grpc).unwrap(), [header(1), header(2), header(3)]);
+        assert_eq!(p2p_headers(&block.encode_to_vec(), P2pFormat::Block).unwrap(), [header(1)]);
+
+        assert!(p2p_headers(&dump[..dump.len() - 1], P2pFormat::Grpc).is_err());
+        assert!(p2p_headers(&[0, 0xff, 0xff, 0xff, 0xff, 0], P2pFormat::Grpc).is_err());
+        dump[0] = 1;
+        assert!(p2p_headers(&dump, P2pFormat::Grpc).is_err());
+    }
+}